@@ -2,7 +2,9 @@ use std::io::Read;
 
 use anyhow::Result;
 use clap::Parser;
-use rust_json_parse::JsonDocument;
+use rust_json_parse::{
+    Backend, JsonDocument, LazyDocument, ParseOptions, Selector, Tape, Utf8Validation,
+};
 
 #[cfg(not(target_arch = "wasm32"))]
 #[global_allocator]
@@ -18,6 +20,34 @@ struct Args {
     file: String,
     #[arg(short, long)]
     serde: bool,
+    /// Deserialize into `serde_json::Value` with `rust_json_parse::from_slice`
+    /// instead of building a `JsonValue`
+    #[arg(short, long)]
+    typed: bool,
+    /// Borrow escape-free strings from the input with `parse_borrowed`
+    #[arg(short, long)]
+    borrowed: bool,
+    /// Use the two-stage structural indexing backend
+    #[arg(long)]
+    structural: bool,
+    /// Validate UTF-8 over the whole input up front instead of per string
+    #[arg(long)]
+    whole_utf8: bool,
+    /// Only parse the value at this `/`-separated path, using `LazyDocument`
+    #[arg(long)]
+    lazy: Option<String>,
+    /// Only parse these comma-separated JSON Pointers, using `Selector`
+    #[arg(long)]
+    select: Option<String>,
+    /// Parse onto a flat `Tape` instead of a `JsonValue` tree
+    #[arg(long)]
+    tape: bool,
+    /// Only check that the input is valid JSON, with `validate`
+    #[arg(long)]
+    validate: bool,
+    /// Force a SIMD kernel, e.g. `scalar`, `sse2`, `avx2` or `avx512`
+    #[arg(long)]
+    kernel: Option<String>,
     #[arg(long)]
     simd: bool,
 }
@@ -32,6 +62,34 @@ struct Args {
     file: String,
     #[arg(short, long)]
     serde: bool,
+    /// Deserialize into `serde_json::Value` with `rust_json_parse::from_slice`
+    /// instead of building a `JsonValue`
+    #[arg(short, long)]
+    typed: bool,
+    /// Borrow escape-free strings from the input with `parse_borrowed`
+    #[arg(short, long)]
+    borrowed: bool,
+    /// Use the two-stage structural indexing backend
+    #[arg(long)]
+    structural: bool,
+    /// Validate UTF-8 over the whole input up front instead of per string
+    #[arg(long)]
+    whole_utf8: bool,
+    /// Only parse the value at this `/`-separated path, using `LazyDocument`
+    #[arg(long)]
+    lazy: Option<String>,
+    /// Only parse these comma-separated JSON Pointers, using `Selector`
+    #[arg(long)]
+    select: Option<String>,
+    /// Parse onto a flat `Tape` instead of a `JsonValue` tree
+    #[arg(long)]
+    tape: bool,
+    /// Only check that the input is valid JSON, with `validate`
+    #[arg(long)]
+    validate: bool,
+    /// Force a SIMD kernel, e.g. `scalar`, `sse2`, `avx2` or `avx512`
+    #[arg(long)]
+    kernel: Option<String>,
 }
 
 #[allow(unused_mut)]
@@ -44,6 +102,9 @@ fn main() -> Result<()> {
     } else {
         std::fs::read(args.file)?
     };
+    if let Some(kernel) = &args.kernel {
+        rust_json_parse::set_simd_kernel(kernel.parse()?)?;
+    }
     #[cfg(any(target_feature = "sse4.2", target_feature = "neon"))]
     if args.simd {
         let _ = simd_json::from_slice::<serde_json::Value>(&mut json)?;
@@ -51,6 +112,42 @@ fn main() -> Result<()> {
     }
     if args.serde {
         let _ = serde_json::from_slice::<serde_json::Value>(&json)?;
+    } else if args.typed {
+        let _ = rust_json_parse::from_slice::<serde_json::Value>(&json)?;
+    } else if args.borrowed {
+        let doc = JsonDocument::init();
+        let _ = rust_json_parse::parse_borrowed(&json, doc.bump())?;
+    } else if args.structural || args.whole_utf8 {
+        let doc = JsonDocument::init();
+        let mut options = ParseOptions::default();
+        if args.structural {
+            options.backend = Backend::Structural;
+        }
+        if args.whole_utf8 {
+            options.utf8 = Utf8Validation::WholeInput;
+        }
+        let _ = rust_json_parse::parse_with_options(&json, doc.bump(), &options)?;
+    } else if args.validate {
+        rust_json_parse::validate(&json)?;
+    } else if args.tape {
+        let _ = Tape::parse(&json)?;
+    } else if let Some(paths) = &args.select {
+        let paths: Vec<&str> = paths.split(',').collect();
+        let doc = JsonDocument::init();
+        let _ = Selector::new(&paths)?.parse(&json, doc.bump())?;
+    } else if let Some(path) = &args.lazy {
+        let doc = LazyDocument::new(&json);
+        let mut value = Some(doc.root());
+        for segment in path.split('/').filter(|s| !s.is_empty()) {
+            value = match (value, segment.parse::<usize>()) {
+                (Some(value), Ok(index)) => value.at(index)?,
+                (Some(value), Err(_)) => value.get(segment)?,
+                (None, _) => None,
+            };
+        }
+        if let Some(value) = value {
+            let _ = value.value()?;
+        }
     } else {
         let _ = JsonDocument::parse_create(&json)?;
     }
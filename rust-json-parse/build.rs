@@ -0,0 +1,24 @@
+use std::env;
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    let minor = rustc_minor().unwrap_or(u32::MAX);
+    // `rustc-check-cfg` is only stable from Rust 1.80
+    if minor >= 80 {
+        println!("cargo:rustc-check-cfg=cfg(avx512_unstable)");
+    }
+    // AVX-512 intrinsics are only stable from Rust 1.89, before which they
+    // need feature gates on nightly
+    if minor < 89 {
+        println!("cargo:rustc-cfg=avx512_unstable");
+    }
+}
+
+/// The minor version of the compiler, like 72 for `rustc 1.72.0-nightly`
+fn rustc_minor() -> Option<u32> {
+    let rustc = env::var_os("RUSTC")?;
+    let output = Command::new(rustc).arg("--version").output().ok()?;
+    let version = String::from_utf8(output.stdout).ok()?;
+    version.split(' ').nth(1)?.split('.').nth(1)?.parse().ok()
+}
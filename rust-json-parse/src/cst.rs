@@ -0,0 +1,711 @@
+//! A lossless concrete syntax tree, for editing hand-written files without
+//! reformatting them.
+//!
+//! Every byte of the input is kept: numbers, strings and literals as they were
+//! written, and whitespace and comments as trivia on the element or member
+//! they come before or after. Writing the tree back out reproduces the input
+//! exactly, and after an edit only the edited span changes.
+
+use std::fmt;
+use std::string::String as StdString;
+use std::vec::Vec as StdVec;
+
+use bumpalo::Bump;
+
+use crate::parsers::json5;
+use crate::parsers::number::read_number;
+use crate::parsers::string::read_string;
+use crate::slice_iter::{CopyIter, SliceIter};
+use crate::{parse_error, parse_with_options, JsonError, JsonResult, JsonValue, ParseError};
+use crate::{ParseOptions, Utf8Validation, MAX_NESTING};
+
+#[derive(thiserror::Error, Debug)]
+pub enum CstError {
+    #[error("{0}")]
+    Json(#[from] JsonError),
+    #[error("Nothing at {path:?}")]
+    NotFound { path: StdString },
+    #[error("Expected an object at {path:?}")]
+    NotAnObject { path: StdString },
+    #[error("Key {key:?} already exists")]
+    KeyExists { key: StdString },
+}
+
+/// A parsed document, with the trivia around its root value.
+///
+/// Comments and trailing commas are accepted, as in JSONC files. Paths are
+/// lists of object keys and array indices, where the last of several
+/// duplicate keys is the one found, as in `JsonValue`.
+///
+/// Arrays and objects nested more than 512 deep are an error, as the parser
+/// recurses into each one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cst {
+    before: StdString,
+    root: Node,
+    after: StdString,
+}
+
+/// A value in a `Cst`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Node {
+    /// A number, string, `true`, `false` or `null`, exactly as written
+    Scalar(StdString),
+    Array(Container),
+    Object(Container),
+}
+
+/// The elements of an array or members of an object
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Container {
+    items: StdVec<Item>,
+    /// Trivia after the last comma, or inside an empty container
+    tail: StdString,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Item {
+    before: StdString,
+    key: Option<Key>,
+    value: Node,
+    /// Trivia between the value and its comma or the closing bracket
+    after: StdString,
+    comma: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Key {
+    /// The key as written, quotes and escapes included
+    raw: StdString,
+    name: StdString,
+    /// Trivia before the colon
+    after: StdString,
+    /// Trivia between the colon and the value
+    after_colon: StdString,
+}
+
+impl Cst {
+    pub fn parse(json: &str) -> Result<Self, JsonError> {
+        let mut parser = Parser {
+            json: SliceIter::new(json.as_bytes()),
+            text: json,
+            scratch: Bump::new(),
+            depth: 0,
+        };
+        let cst = parser
+            .document()
+            .map_err(|e| parse_error(e, &json.as_bytes()[parser.json.index()..]))?;
+        if let Some(found) = parser.json.peek_copy() {
+            return Err(parse_error(
+                ParseError::TrailingCharacters {
+                    found: found as char,
+                },
+                &json.as_bytes()[parser.json.index()..],
+            ));
+        }
+        Ok(cst)
+    }
+
+    pub fn root(&self) -> &Node {
+        &self.root
+    }
+
+    pub fn get(&self, path: &[&str]) -> Option<&Node> {
+        let mut node = &self.root;
+        for segment in path {
+            let (container, index) = node.find(segment)?;
+            node = &container.items[index].value;
+        }
+        Some(node)
+    }
+
+    /// Replaces the value at `path` with `json`, keeping the trivia around it
+    pub fn set(&mut self, path: &[&str], json: &str) -> Result<(), CstError> {
+        let value = Cst::parse(json)?.root;
+        let node = self.get_mut(path).ok_or_else(|| not_found(path))?;
+        *node = value;
+        Ok(())
+    }
+
+    /// Adds `key` with the value `json` to the end of the object at `path`,
+    /// following the indentation of the members already there
+    pub fn insert(&mut self, path: &[&str], key: &str, json: &str) -> Result<(), CstError> {
+        let value = Cst::parse(json)?.root;
+        let container = match self.get_mut(path) {
+            Some(Node::Object(container)) => container,
+            Some(_) => {
+                return Err(CstError::NotAnObject {
+                    path: path.join("/"),
+                })
+            }
+            None => return Err(not_found(path)),
+        };
+        if container.position(key).is_some() {
+            return Err(CstError::KeyExists {
+                key: key.to_owned(),
+            });
+        }
+        let key = Key {
+            raw: quote(key),
+            name: key.to_owned(),
+            after: StdString::new(),
+            after_colon: " ".to_owned(),
+        };
+        container.push(key, value);
+        Ok(())
+    }
+
+    /// Removes the array element or object member at `path`, along with the
+    /// comments on the lines before it and the rest of its line
+    pub fn remove(&mut self, path: &[&str]) -> Result<Node, CstError> {
+        let (last, parent) = path.split_last().ok_or_else(|| not_found(path))?;
+        let (container, index) = self
+            .get_mut(parent)
+            .and_then(|node| node.find_mut(last))
+            .ok_or_else(|| not_found(path))?;
+        Ok(container.remove(index))
+    }
+
+    fn get_mut(&mut self, path: &[&str]) -> Option<&mut Node> {
+        let mut node = &mut self.root;
+        for segment in path {
+            let (container, index) = node.find_mut(segment)?;
+            node = &mut container.items[index].value;
+        }
+        Some(node)
+    }
+}
+
+impl fmt::Display for Cst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}{}", self.before, self.root, self.after)
+    }
+}
+
+impl Node {
+    /// Parses the node into a `JsonValue`
+    pub fn to_value<'bump>(&self, allocator: &'bump Bump) -> Result<JsonValue<'bump>, JsonError> {
+        let options = ParseOptions {
+            utf8: Utf8Validation::WholeInput,
+            comments: true,
+            trailing_commas: true,
+            ..Default::default()
+        };
+        parse_with_options(self.to_string().as_bytes(), allocator, &options)
+    }
+
+    fn find(&self, segment: &str) -> Option<(&Container, usize)> {
+        match self {
+            Node::Array(container) => segment
+                .parse::<usize>()
+                .ok()
+                .filter(|index| *index < container.items.len())
+                .map(|index| (container, index)),
+            Node::Object(container) => Some((container, container.position(segment)?)),
+            Node::Scalar(_) => None,
+        }
+    }
+
+    fn find_mut(&mut self, segment: &str) -> Option<(&mut Container, usize)> {
+        let index = self.find(segment)?.1;
+        match self {
+            Node::Array(container) | Node::Object(container) => Some((container, index)),
+            Node::Scalar(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Node::Scalar(raw) => f.write_str(raw),
+            Node::Array(container) => write!(f, "[{container}]"),
+            Node::Object(container) => write!(f, "{{{container}}}"),
+        }
+    }
+}
+
+impl Container {
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.items
+            .iter()
+            .rposition(|item| item.key.as_ref().map_or(false, |key| key.name == name))
+    }
+
+    fn push(&mut self, key: Key, value: Node) {
+        let indent = self.indentation().to_owned();
+        let mut item = Item {
+            before: StdString::new(),
+            key: Some(key),
+            value,
+            after: StdString::new(),
+            comma: false,
+        };
+        match self.items.last_mut() {
+            None => {
+                let (line, rest) = split_line(&self.tail);
+                item.before = format!("{line}{rest}");
+                if rest.contains('\n') {
+                    item.before.push_str("  ");
+                }
+                self.tail = rest.to_owned();
+            }
+            // With a trailing comma, the new member gets one too
+            Some(last) if last.comma => {
+                let (line, rest) = split_line(&self.tail);
+                item.before = after_line(line, &indent);
+                item.comma = true;
+                self.tail = rest.to_owned();
+            }
+            // Otherwise the comma goes straight after the last value, with
+            // any comment after it staying on its line
+            Some(last) => {
+                let (line, rest) = split_line(&last.after);
+                item.before = after_line(line, &indent);
+                item.after = rest.to_owned();
+                last.after.clear();
+                last.comma = true;
+            }
+        }
+        self.items.push(item);
+    }
+
+    /// The trivia to put before a new item: a line break and the last item's
+    /// indentation, or for containers on one line, the last item's spacing
+    fn indentation(&self) -> &str {
+        let Some(last) = self.items.last() else {
+            return "";
+        };
+        match last.before.rfind('\n') {
+            Some(i) if last.before[..i].ends_with('\r') => &last.before[i - 1..],
+            Some(i) => &last.before[i..],
+            None if self.items.len() > 1 && last.before.trim().is_empty() => &last.before,
+            None => " ",
+        }
+    }
+
+    fn remove(&mut self, index: usize) -> Node {
+        let removed = self.items.remove(index);
+        let (removed_line, _) = split_line(&removed.before);
+        if self.items.is_empty() {
+            let tail = if removed.comma {
+                &self.tail
+            } else {
+                &removed.after
+            };
+            self.tail = split_line(tail).1.to_owned();
+        } else if index == self.items.len() {
+            // Keep the comment after the previous item's comma
+            if removed.comma {
+                self.tail = format!("{removed_line}{}", self.tail);
+            } else {
+                let prev = self.items.last_mut().unwrap();
+                prev.after = format!(
+                    "{}{removed_line}{}",
+                    prev.after,
+                    split_line(&removed.after).1
+                );
+                prev.comma = false;
+            }
+        } else {
+            let next = &mut self.items[index];
+            if !removed.before.contains('\n') && !next.before.contains('\n') {
+                next.before = removed.before;
+            } else {
+                next.before = format!("{removed_line}{}", split_line(&next.before).1);
+            }
+        }
+        removed.value
+    }
+}
+
+impl fmt::Display for Container {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for item in &self.items {
+            f.write_str(&item.before)?;
+            if let Some(key) = &item.key {
+                write!(f, "{}{}:{}", key.raw, key.after, key.after_colon)?;
+            }
+            write!(f, "{}{}", item.value, item.after)?;
+            if item.comma {
+                f.write_str(",")?;
+            }
+        }
+        f.write_str(&self.tail)
+    }
+}
+
+/// Splits trivia into what is still on the line it starts on, and everything
+/// from the first line break. Whitespace alone on a line counts as nothing,
+/// and trivia without a line break that is only whitespace is all put in
+/// the second part.
+fn split_line(trivia: &str) -> (&str, &str) {
+    let (line, rest) = match trivia.find('\n') {
+        Some(i) if trivia[..i].ends_with('\r') => trivia.split_at(i - 1),
+        Some(i) => trivia.split_at(i),
+        None if trivia.trim().is_empty() => ("", trivia),
+        None => (trivia, ""),
+    };
+    if line.trim().is_empty() {
+        return ("", rest);
+    }
+    (line, rest)
+}
+
+/// Joins the rest of a line with the indentation of the next item, starting a
+/// new line if the rest is a `//` comment
+fn after_line(line: &str, indent: &str) -> StdString {
+    if line.contains("//") && !indent.contains('\n') {
+        return format!("{line}\n{indent}");
+    }
+    format!("{line}{indent}")
+}
+
+/// Writes `key` as a JSON string
+fn quote(key: &str) -> StdString {
+    let mut quoted = StdString::with_capacity(key.len() + 2);
+    quoted.push('"');
+    for char in key.chars() {
+        match char {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            char if (char as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", char as u32)),
+            char => quoted.push(char),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn not_found(path: &[&str]) -> CstError {
+    CstError::NotFound {
+        path: path.join("/"),
+    }
+}
+
+struct Parser<'a> {
+    json: SliceIter<'a, u8>,
+    text: &'a str,
+    /// Strings are decoded here only to check them
+    scratch: Bump,
+    /// How many arrays and objects it is inside of
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn document(&mut self) -> JsonResult<Cst> {
+        let before = self.trivia()?;
+        let root = self.value()?;
+        let after = self.trivia()?;
+        Ok(Cst {
+            before,
+            root,
+            after,
+        })
+    }
+
+    fn trivia(&mut self) -> JsonResult<StdString> {
+        let start = self.json.index();
+        json5::skip_whitespace(&mut self.json, false)?;
+        Ok(self.text[start..self.json.index()].to_owned())
+    }
+
+    /// The input from `start` up to where the parser is now
+    fn since(&self, start: usize) -> StdString {
+        self.text[start..self.json.index()].to_owned()
+    }
+
+    fn value(&mut self) -> JsonResult<Node> {
+        let start = self.json.index();
+        match self.json.peek_copy() {
+            Some(b'[') => return Ok(Node::Array(self.container(b']', false)?)),
+            Some(b'{') => return Ok(Node::Object(self.container(b'}', true)?)),
+            Some(b'"') => {
+                self.scratch.reset();
+                read_string(&mut self.json, &self.scratch)?;
+            }
+            Some(b'-' | b'0'..=b'9') => {
+                read_number(&mut self.json)?;
+            }
+            Some(_) => {
+                let literal = [&b"true"[..], b"false", b"null"]
+                    .into_iter()
+                    .find(|literal| self.json.peek_many_ref(literal.len()) == Some(literal));
+                match literal {
+                    Some(literal) => self.json.ignore_many(literal.len()),
+                    None => {
+                        return Err(ParseError::ExpectedNextValue {
+                            found: self.json.peek_copy().map(|c| c as char),
+                        })
+                    }
+                }
+            }
+            None => return Err(ParseError::UnexpectedEndOfFile),
+        }
+        Ok(Node::Scalar(self.since(start)))
+    }
+
+    fn container(&mut self, close: u8, keyed: bool) -> JsonResult<Container> {
+        if self.depth == MAX_NESTING {
+            let limit = MAX_NESTING;
+            return Err(ParseError::NestingTooDeep { limit });
+        }
+        self.depth += 1;
+        let container = self.items(close, keyed);
+        self.depth -= 1;
+        container
+    }
+
+    fn items(&mut self, close: u8, keyed: bool) -> JsonResult<Container> {
+        self.json.ignore_next();
+        let mut items = StdVec::new();
+        loop {
+            let before = self.trivia()?;
+            if self.json.peek_copy() == Some(close) {
+                self.json.ignore_next();
+                return Ok(Container {
+                    items,
+                    tail: before,
+                });
+            }
+            let key = if keyed { Some(self.key()?) } else { None };
+            let value = self.value()?;
+            let after = self.trivia()?;
+            let comma = match self.json.next() {
+                Some(b',') => true,
+                Some(c) if c == close => false,
+                found if keyed => {
+                    return Err(ParseError::ExpectedEndOfObject {
+                        found: found.map(|c| c as char),
+                    })
+                }
+                found => {
+                    return Err(ParseError::ExpectedEndOfArray {
+                        found: found.map(|c| c as char),
+                    })
+                }
+            };
+            items.push(Item {
+                before,
+                key,
+                value,
+                after,
+                comma,
+            });
+            if !comma {
+                return Ok(Container {
+                    items,
+                    tail: StdString::new(),
+                });
+            }
+        }
+    }
+
+    fn key(&mut self) -> JsonResult<Key> {
+        let start = self.json.index();
+        self.scratch.reset();
+        let name = read_string(&mut self.json, &self.scratch)?
+            .as_str()
+            .to_owned();
+        let raw = self.since(start);
+        let after = self.trivia()?;
+        let c = self.json.next();
+        if c != Some(b':') {
+            return Err(ParseError::ExpectedColon {
+                found: c.map(|c| c as char),
+            });
+        }
+        let after_colon = self.trivia()?;
+        Ok(Key {
+            raw,
+            name,
+            after,
+            after_colon,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    const SETTINGS: &str = r#"// Editor settings
+{
+    /* Font */
+    "editor.fontSize": 14, // points
+    "editor.fontFamily": "Fira Code",
+    "files.exclude": {
+        "**/.git": true,
+        "**/node_modules": true,
+    },
+    "list": [1,  2.50, 3e0], // odd numbers
+    "empty": {}
+}
+"#;
+
+    #[test]
+    fn round_trip() {
+        let food = std::str::from_utf8(include_bytes!("../../tests/food.json")).unwrap();
+        for json in [
+            SETTINGS,
+            food,
+            "1",
+            " \"\\u0041\" ",
+            "[]",
+            "{ }",
+            "[1,]\r\n",
+            "/* a */ null // b",
+        ] {
+            let cst = Cst::parse(json).unwrap();
+            assert_eq!(cst.to_string(), json);
+        }
+        let bump = Bump::new();
+        assert_eq!(
+            Cst::parse(food).unwrap().root().to_value(&bump).unwrap(),
+            parse(food.as_bytes(), &bump).unwrap()
+        );
+        for json in ["", "[1 2]", "{\"a\" 1}", "[1] 2", "/* a", "[01]", "\"\t\""] {
+            assert!(Cst::parse(json).is_err(), "{json:?}");
+        }
+    }
+
+    #[test]
+    fn set() {
+        let mut cst = Cst::parse(SETTINGS).unwrap();
+        cst.set(&["editor.fontSize"], "16").unwrap();
+        cst.set(&["list", "1"], "[2, 2]").unwrap();
+        cst.set(&["files.exclude", "**/.git"], "false").unwrap();
+        let expected = SETTINGS
+            .replace("14, // points", "16, // points")
+            .replace("2.50", "[2, 2]")
+            .replace("\"**/.git\": true", "\"**/.git\": false");
+        assert_eq!(cst.to_string(), expected);
+        assert_eq!(cst.get(&["list", "1", "0"]).unwrap().to_string(), "2");
+
+        assert!(matches!(
+            cst.set(&["missing"], "1"),
+            Err(CstError::NotFound { .. })
+        ));
+        assert!(matches!(
+            cst.set(&["list", "3"], "1"),
+            Err(CstError::NotFound { .. })
+        ));
+        assert!(matches!(cst.set(&["list"], "[1"), Err(CstError::Json(_))));
+        cst.set(&[], "null").unwrap();
+        assert_eq!(cst.to_string(), "// Editor settings\nnull\n");
+    }
+
+    #[test]
+    fn insert() {
+        let mut cst = Cst::parse(SETTINGS).unwrap();
+        cst.insert(&[], "new \"key\"", "[true]").unwrap();
+        cst.insert(&["files.exclude"], "**/target", "true").unwrap();
+        cst.insert(&["empty"], "a", "1").unwrap();
+        let expected = SETTINGS
+            .replace(
+                "\"empty\": {}\n",
+                "\"empty\": {\"a\": 1},\n    \"new \\\"key\\\"\": [true]\n",
+            )
+            .replace(
+                "\"**/node_modules\": true,\n",
+                "\"**/node_modules\": true,\n        \"**/target\": true,\n",
+            );
+        assert_eq!(cst.to_string(), expected);
+
+        let mut cst = Cst::parse("{\"a\": 1 // one\n}").unwrap();
+        cst.insert(&[], "b", "2").unwrap();
+        assert_eq!(cst.to_string(), "{\"a\": 1, // one\n \"b\": 2\n}");
+        for (json, expected) in [
+            ("{ \"a\": 1 }", "{ \"a\": 1, \"b\": 2 }"),
+            ("{\"a\":1,\"c\":3}", "{\"a\":1,\"c\":3,\"b\": 2}"),
+            ("{ }", "{ \"b\": 2 }"),
+            ("{\n}", "{\n  \"b\": 2\n}"),
+            (
+                "{\r\n  \"a\": 1\r\n}",
+                "{\r\n  \"a\": 1,\r\n  \"b\": 2\r\n}",
+            ),
+        ] {
+            let mut cst = Cst::parse(json).unwrap();
+            cst.insert(&[], "b", "2").unwrap();
+            assert_eq!(cst.to_string(), expected);
+        }
+
+        assert!(matches!(
+            cst.insert(&[], "b", "3"),
+            Err(CstError::KeyExists { .. })
+        ));
+        assert!(matches!(
+            cst.insert(&["b"], "c", "3"),
+            Err(CstError::NotAnObject { .. })
+        ));
+    }
+
+    #[test]
+    fn deep() {
+        let nested = |depth: usize| "[".repeat(depth) + &"]".repeat(depth);
+        let json = nested(MAX_NESTING);
+        assert_eq!(Cst::parse(&json).unwrap().to_string(), json);
+        for json in [nested(MAX_NESTING + 1), "[".repeat(200_000)] {
+            assert!(matches!(
+                Cst::parse(&json),
+                Err(JsonError::ParseError(
+                    ParseError::NestingTooDeep { limit: MAX_NESTING },
+                    _
+                ))
+            ));
+        }
+    }
+
+    #[test]
+    fn remove() {
+        let mut cst = Cst::parse(SETTINGS).unwrap();
+        assert_eq!(cst.remove(&["editor.fontSize"]).unwrap().to_string(), "14");
+        cst.remove(&["files.exclude", "**/node_modules"]).unwrap();
+        cst.remove(&["list", "0"]).unwrap();
+        cst.remove(&["empty"]).unwrap();
+        let expected = SETTINGS
+            .replace(
+                "    /* Font */\n    \"editor.fontSize\": 14, // points\n",
+                "",
+            )
+            .replace("        \"**/node_modules\": true,\n", "")
+            .replace("[1,  2.50", "[2.50")
+            .replace(
+                "3e0], // odd numbers\n    \"empty\": {}\n",
+                "3e0] // odd numbers\n",
+            );
+        assert_eq!(cst.to_string(), expected);
+
+        for (json, path, expected) in [
+            ("[1, 2, 3]", "1", "[1, 3]"),
+            ("[1, 2, 3]", "2", "[1, 2]"),
+            ("[1]", "0", "[]"),
+            ("[\n  1\n]", "0", "[\n]"),
+            (
+                "[\n  0, // zero\n  1, // one\n  // about two\n  2\n]",
+                "1",
+                "[\n  0, // zero\n  // about two\n  2\n]",
+            ),
+            ("[\n  0, // zero\n  1 // one\n]", "1", "[\n  0 // zero\n]"),
+            ("[\n  0, // zero\n  1,\n]", "1", "[\n  0, // zero\n]"),
+        ] {
+            let mut cst = Cst::parse(json).unwrap();
+            cst.remove(&[path]).unwrap();
+            assert_eq!(cst.to_string(), expected, "{json:?} {path}");
+        }
+        assert!(matches!(cst.remove(&[]), Err(CstError::NotFound { .. })));
+        assert!(matches!(cst.remove(&["5"]), Err(CstError::NotFound { .. })));
+    }
+}
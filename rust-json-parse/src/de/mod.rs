@@ -0,0 +1,153 @@
+//! Serde support for reading typed structs out of JSON.
+
+use std::fmt::Display;
+use std::string::String as StdString;
+
+use serde::de::value::BorrowedStrDeserializer;
+use serde::de::{self, IntoDeserializer, Unexpected, Visitor};
+use serde::{forward_to_deserialize_any, Deserializer};
+
+use crate::ParseError;
+
+mod slice;
+mod value;
+
+pub use slice::from_slice;
+pub use value::from_value;
+
+/// An error raised while deserializing into a Rust type.
+///
+/// `path` is a JSON Pointer to the value that failed, e.g. `/items/3/sku`.
+/// Boxed so that the `Result`s passed around while deserializing stay small.
+#[derive(thiserror::Error, Debug)]
+#[error("{} at {:?}", .0.message, .0.path)]
+pub struct DeError(Box<ErrorInner>);
+
+#[derive(Debug)]
+struct ErrorInner {
+    message: StdString,
+    path: StdString,
+}
+
+impl DeError {
+    pub fn message(&self) -> &str {
+        &self.0.message
+    }
+    pub fn path(&self) -> &str {
+        &self.0.path
+    }
+
+    pub(crate) fn syntax(err: ParseError, offset: usize) -> Self {
+        de::Error::custom(format_args!("{err} (byte {offset})"))
+    }
+
+    /// Prefixes the path with the key or index of the enclosing value, as the
+    /// error travels back up the tree.
+    pub(crate) fn prepend<T: Display>(mut self, token: T) -> Self {
+        let token = token.to_string().replace('~', "~0").replace('/', "~1");
+        self.0.path.insert_str(0, &token);
+        self.0.path.insert(0, '/');
+        self
+    }
+}
+
+impl de::Error for DeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self(Box::new(ErrorInner {
+            message: msg.to_string(),
+            path: StdString::new(),
+        }))
+    }
+}
+
+/// Hands whole numbers to the visitor as integers, so integer fields can be
+/// read from the `f64` every JSON number is stored as.
+fn visit_number<'de, V: Visitor<'de>>(num: f64, visitor: V) -> Result<V::Value, DeError> {
+    if num.fract() == 0.0 {
+        if (0.0..18446744073709551616.0).contains(&num) {
+            return visitor.visit_u64(num as u64);
+        }
+        if (-9223372036854775808.0..0.0).contains(&num) {
+            return visitor.visit_i64(num as i64);
+        }
+    }
+    visitor.visit_f64(num)
+}
+
+/// Object keys are always strings, but may be read into integer map keys
+/// such as `HashMap<u32, _>`.
+enum KeyDeserializer<'de, 's> {
+    Borrowed(&'de str),
+    Transient(&'s str),
+}
+
+impl<'de, 's> KeyDeserializer<'de, 's> {
+    fn as_str(&self) -> &str {
+        match self {
+            KeyDeserializer::Borrowed(str) => str,
+            KeyDeserializer::Transient(str) => str,
+        }
+    }
+}
+
+macro_rules! deserialize_parsed_key {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+                match self.as_str().parse() {
+                    Ok(num) => visitor.$visit(num),
+                    Err(_) => Err(de::Error::invalid_value(Unexpected::Str(self.as_str()), &visitor)),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de, 's> Deserializer<'de> for KeyDeserializer<'de, 's> {
+    type Error = DeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        match self {
+            KeyDeserializer::Borrowed(str) => visitor.visit_borrowed_str(str),
+            KeyDeserializer::Transient(str) => visitor.visit_str(str),
+        }
+    }
+
+    deserialize_parsed_key! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        match self {
+            KeyDeserializer::Borrowed(str) => {
+                visitor.visit_enum(BorrowedStrDeserializer::<DeError>::new(str))
+            }
+            KeyDeserializer::Transient(str) => visitor.visit_enum(str.into_deserializer()),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i128 u128 f32 f64 char str string bytes byte_buf option unit
+        unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
@@ -0,0 +1,504 @@
+use bumpalo::Bump;
+use serde::de::value::BorrowedStrDeserializer;
+use serde::de::{
+    self, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess, VariantAccess,
+    Visitor,
+};
+use serde::{forward_to_deserialize_any, Deserialize, Deserializer};
+
+use super::{visit_number, DeError, KeyDeserializer};
+use crate::parsers::number::read_number;
+use crate::parsers::string::{read_borrowed_str, read_string};
+use crate::slice_iter::{CopyIter, SliceIter};
+use crate::{ignore_ws, is_number, JsonResult, ParseError, MAX_NESTING};
+
+/// Deserializes a `T` straight from JSON text, without building a `JsonValue`
+/// tree first.
+///
+/// Strings without escapes are borrowed from `slice` where `T` allows it.
+/// Integers that fit a `u64` or `i64` are read exactly, and other numbers as
+/// `f64`s. Arrays and objects nested more than 512 deep are an error, as
+/// they would overflow the stack.
+pub fn from_slice<'de, T: Deserialize<'de>>(slice: &'de [u8]) -> Result<T, DeError> {
+    let mut de = SliceDeserializer {
+        json: SliceIter::new(slice),
+        scratch: Bump::new(),
+        depth: 0,
+    };
+    let value = T::deserialize(&mut de)?;
+    ignore_ws(&mut de.json);
+    match de.json.peek_copy() {
+        None => Ok(value),
+        Some(_) => Err(de::Error::custom(format_args!(
+            "trailing characters after the value (byte {})",
+            de.json.index()
+        ))),
+    }
+}
+
+struct SliceDeserializer<'de> {
+    json: SliceIter<'de, u8>,
+    /// Holds the unescaped copy of strings that can't be borrowed, until the
+    /// visitor is done with them
+    scratch: Bump,
+    /// How many arrays and objects it is inside of
+    depth: usize,
+}
+
+impl<'de> SliceDeserializer<'de> {
+    fn error(&self, err: ParseError) -> DeError {
+        DeError::syntax(err, self.json.index())
+    }
+
+    fn check<T>(&self, result: JsonResult<T>) -> Result<T, DeError> {
+        result.map_err(|e| self.error(e))
+    }
+
+    fn expect(&mut self, byte: u8, err: fn(Option<char>) -> ParseError) -> Result<(), DeError> {
+        ignore_ws(&mut self.json);
+        match self.json.peek_copy() {
+            Some(b) if b == byte => {
+                self.json.ignore_next();
+                Ok(())
+            }
+            found => Err(self.error(err(found.map(|b| b as char)))),
+        }
+    }
+
+    fn expect_literal(&mut self, literal: &[u8]) -> Result<(), DeError> {
+        if self.json.peek_many_ref(literal.len()) == Some(literal) {
+            self.json.ignore_many(literal.len());
+            Ok(())
+        } else {
+            let found = self.json.peek_copy().map(|b| b as char);
+            Err(self.error(ParseError::ExpectedNextValue { found }))
+        }
+    }
+
+    /// Steps into an array or object, unless that would be too deep
+    fn enter(&mut self) -> Result<(), DeError> {
+        if self.depth == MAX_NESTING {
+            let limit = MAX_NESTING;
+            return Err(self.error(ParseError::NestingTooDeep { limit }));
+        }
+        self.depth += 1;
+        self.json.ignore_next();
+        Ok(())
+    }
+
+    /// Hands an integer that fits a `u64` or `i64` to the visitor as one,
+    /// and anything else as an `f64`
+    fn visit_number<V: Visitor<'de>>(&mut self, visitor: V) -> Result<V::Value, DeError> {
+        let bytes = &self.json.as_slice()[self.json.index()..];
+        let negative = bytes.first() == Some(&b'-');
+        let digits = &bytes[negative as usize..];
+        let len = digits.iter().take_while(|b| b.is_ascii_digit()).count();
+        let integer = len > 0
+            && (len == 1 || digits[0] != b'0')
+            && !matches!(digits.get(len), Some(b'.' | b'e' | b'E'));
+        if integer {
+            let magnitude = digits[..len].iter().try_fold(0u64, |n, digit| {
+                n.checked_mul(10)?.checked_add((digit - b'0') as u64)
+            });
+            let end = negative as usize + len;
+            match magnitude {
+                Some(n) if !negative => {
+                    self.json.ignore_many(end);
+                    return visitor.visit_u64(n);
+                }
+                // `-0` is left to the `f64` below, to keep its sign
+                Some(n) if n != 0 && n <= i64::MIN.unsigned_abs() => {
+                    self.json.ignore_many(end);
+                    return visitor.visit_i64((n as i64).wrapping_neg());
+                }
+                _ => {}
+            }
+        }
+        let num = read_number(&mut self.json);
+        visit_number(self.check(num)?, visitor)
+    }
+
+    fn visit_str<V: Visitor<'de>>(&mut self, visitor: V, bytes: bool) -> Result<V::Value, DeError> {
+        let borrowed = read_borrowed_str(&mut self.json);
+        if let Some(str) = self.check(borrowed)? {
+            return match bytes {
+                true => visitor.visit_borrowed_bytes(str.as_bytes()),
+                false => visitor.visit_borrowed_str(str),
+            };
+        }
+        let string = read_string(&mut self.json, &self.scratch);
+        let string = self.check(string)?;
+        let ret = match bytes {
+            true => visitor.visit_bytes(string.as_bytes()),
+            false => visitor.visit_str(&string),
+        };
+        drop(string);
+        self.scratch.reset();
+        ret
+    }
+
+    /// Re-reads the key of the member that failed, to add it to the error path
+    fn key_at(&self, offset: usize) -> std::string::String {
+        let mut json = SliceIter::new(self.json.as_slice());
+        json.set_index(offset);
+        let bump = Bump::new();
+        let key = read_string(&mut json, &bump).map(|key| key.as_str().to_owned());
+        key.unwrap_or_default()
+    }
+}
+
+impl<'a, 'de> Deserializer<'de> for &'a mut SliceDeserializer<'de> {
+    type Error = DeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        ignore_ws(&mut self.json);
+        match self.json.peek_copy() {
+            Some(b'"') => self.visit_str(visitor, false),
+            Some(b'[') => {
+                self.enter()?;
+                let value = visitor.visit_seq(ArrayParser {
+                    de: &mut *self,
+                    index: 0,
+                })?;
+                self.expect(b']', |found| ParseError::ExpectedEndOfArray { found })?;
+                self.depth -= 1;
+                Ok(value)
+            }
+            Some(b'{') => {
+                self.enter()?;
+                let value = visitor.visit_map(ObjectParser {
+                    de: &mut *self,
+                    first: true,
+                    key: 0,
+                })?;
+                self.expect(b'}', |found| ParseError::ExpectedEndOfObject { found })?;
+                self.depth -= 1;
+                Ok(value)
+            }
+            Some(b't') => {
+                self.expect_literal(b"true")?;
+                visitor.visit_bool(true)
+            }
+            Some(b'f') => {
+                self.expect_literal(b"false")?;
+                visitor.visit_bool(false)
+            }
+            Some(b'n') => {
+                self.expect_literal(b"null")?;
+                visitor.visit_unit()
+            }
+            Some(char) if is_number(char) => self.visit_number(visitor),
+            Some(char) => Err(self.error(ParseError::ExpectedNextValue {
+                found: Some(char as char),
+            })),
+            None => Err(self.error(ParseError::UnexpectedEndOfFile)),
+        }
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        self.deserialize_f64(visitor)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        ignore_ws(&mut self.json);
+        match self.json.peek_copy() {
+            Some(char) if is_number(char) => {
+                let num = read_number(&mut self.json);
+                visitor.visit_f64(self.check(num)?)
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        ignore_ws(&mut self.json);
+        match self.json.peek_copy() {
+            Some(b'"') => self.visit_str(visitor, true),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        ignore_ws(&mut self.json);
+        match self.json.peek_copy() {
+            Some(b'n') => {
+                self.expect_literal(b"null")?;
+                visitor.visit_none()
+            }
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        ignore_ws(&mut self.json);
+        match self.json.peek_copy() {
+            Some(b'"') => {
+                let borrowed = read_borrowed_str(&mut self.json);
+                if let Some(str) = self.check(borrowed)? {
+                    return visitor.visit_enum(BorrowedStrDeserializer::<DeError>::new(str));
+                }
+                let string = read_string(&mut self.json, &self.scratch);
+                let string = self.check(string)?;
+                let ret = visitor.visit_enum(string.as_str().into_deserializer());
+                drop(string);
+                self.scratch.reset();
+                ret
+            }
+            Some(b'{') => {
+                self.enter()?;
+                ignore_ws(&mut self.json);
+                let value = visitor.visit_enum(VariantParser { de: &mut *self })?;
+                self.expect(b'}', |found| ParseError::ExpectedEndOfObject { found })?;
+                self.depth -= 1;
+                Ok(value)
+            }
+            _ => Err(de::Error::custom(
+                "expected a string or an object with a single key",
+            )),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 char str string
+        unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct ArrayParser<'a, 'de> {
+    de: &'a mut SliceDeserializer<'de>,
+    index: usize,
+}
+
+impl<'a, 'de> SeqAccess<'de> for ArrayParser<'a, 'de> {
+    type Error = DeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, DeError> {
+        ignore_ws(&mut self.de.json);
+        match self.de.json.peek_copy() {
+            Some(b']') => return Ok(None),
+            Some(b',') if self.index > 0 => self.de.json.ignore_next(),
+            found if self.index > 0 => {
+                return Err(self.de.error(ParseError::ExpectedEndOfArray {
+                    found: found.map(|b| b as char),
+                }))
+            }
+            _ => {}
+        }
+        let index = self.index;
+        self.index += 1;
+        seed.deserialize(&mut *self.de)
+            .map(Some)
+            .map_err(|e| e.prepend(index))
+    }
+}
+
+struct ObjectParser<'a, 'de> {
+    de: &'a mut SliceDeserializer<'de>,
+    first: bool,
+    /// Offset of the current member's key, only read again if its value fails
+    key: usize,
+}
+
+impl<'a, 'de> MapAccess<'de> for ObjectParser<'a, 'de> {
+    type Error = DeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, DeError> {
+        ignore_ws(&mut self.de.json);
+        match self.de.json.peek_copy() {
+            Some(b'}') => return Ok(None),
+            Some(b',') if !self.first => {
+                self.de.json.ignore_next();
+                ignore_ws(&mut self.de.json);
+            }
+            found if !self.first => {
+                return Err(self.de.error(ParseError::ExpectedEndOfObject {
+                    found: found.map(|b| b as char),
+                }))
+            }
+            _ => {}
+        }
+        self.first = false;
+        self.key = self.de.json.index();
+
+        let de = &mut *self.de;
+        let borrowed = read_borrowed_str(&mut de.json);
+        let key = match de.check(borrowed)? {
+            Some(str) => seed.deserialize(KeyDeserializer::Borrowed(str)),
+            None => {
+                let string = read_string(&mut de.json, &de.scratch);
+                let string = de.check(string)?;
+                let ret = seed.deserialize(KeyDeserializer::Transient(&string));
+                drop(string);
+                de.scratch.reset();
+                ret
+            }
+        };
+        let key = key.map_err(|e| e.prepend(self.de.key_at(self.key)))?;
+        self.de
+            .expect(b':', |found| ParseError::ExpectedColon { found })?;
+        Ok(Some(key))
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, DeError> {
+        seed.deserialize(&mut *self.de)
+            .map_err(|e| e.prepend(self.de.key_at(self.key)))
+    }
+}
+
+struct VariantParser<'a, 'de> {
+    de: &'a mut SliceDeserializer<'de>,
+}
+
+impl<'a, 'de> EnumAccess<'de> for VariantParser<'a, 'de> {
+    type Error = DeError;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), DeError> {
+        let variant = seed.deserialize(&mut *self.de)?;
+        self.de
+            .expect(b':', |found| ParseError::ExpectedColon { found })?;
+        Ok((variant, self))
+    }
+}
+
+impl<'a, 'de> VariantAccess<'de> for VariantParser<'a, 'de> {
+    type Error = DeError;
+
+    fn unit_variant(self) -> Result<(), DeError> {
+        ignore_ws(&mut self.de.json);
+        self.de.expect_literal(b"null")
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, DeError> {
+        seed.deserialize(self.de)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, DeError> {
+        self.de.deserialize_seq(visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        self.de.deserialize_map(visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::collections::HashMap;
+
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Event<'a> {
+        id: u64,
+        #[serde(borrow)]
+        name: Cow<'a, str>,
+        tags: Vec<&'a str>,
+        kind: Kind,
+        score: Option<f32>,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    enum Kind {
+        Click,
+        Scroll { by: i32 },
+    }
+
+    #[test]
+    fn typed_struct() {
+        let json = br#" {"id": 7, "name": "caf\u00e9", "tags": ["a", "b"],
+            "kind": {"Scroll": {"by": -3}}, "score": null, "ignored": [{"x": [1, 2]}]} "#;
+        let event: Event = from_slice(json).unwrap();
+        assert_eq!(event.id, 7);
+        assert_eq!(event.name, "café");
+        assert!(matches!(event.name, Cow::Owned(_)));
+        assert_eq!(event.tags, ["a", "b"]);
+        assert_eq!(event.kind, Kind::Scroll { by: -3 });
+        assert_eq!(event.score, None);
+
+        let kinds: Vec<Kind> = from_slice(br#"["Click", {"Scroll":{"by":1}}]"#).unwrap();
+        assert_eq!(kinds, [Kind::Click, Kind::Scroll { by: 1 }]);
+    }
+
+    #[test]
+    fn errors() {
+        let err = from_slice::<HashMap<String, Vec<u8>>>(br#"{"a": [1, 2], "b\/": [3, 300]}"#)
+            .unwrap_err();
+        assert_eq!(err.path(), "/b~1/1");
+        let err = from_slice::<Vec<u8>>(b"[1, 2 3]").unwrap_err();
+        assert_eq!(err.path(), "");
+        assert!(from_slice::<Vec<u8>>(b"[1, 2] 3").is_err());
+        assert!(from_slice::<Vec<u8>>(b"[1, 2").is_err());
+    }
+
+    #[test]
+    fn integers() {
+        #[derive(Deserialize)]
+        struct Id {
+            id: u64,
+        }
+        let id: Id = from_slice(br#"{"id": 9007199254740993}"#).unwrap();
+        assert_eq!(id.id, 9007199254740993);
+        assert_eq!(
+            from_slice::<u64>(b"18446744073709551615").unwrap(),
+            u64::MAX
+        );
+        assert_eq!(
+            from_slice::<i64>(b"-9223372036854775807").unwrap(),
+            -9223372036854775807
+        );
+        assert_eq!(
+            from_slice::<i64>(b"-9223372036854775808").unwrap(),
+            i64::MIN
+        );
+        assert!(from_slice::<u64>(b"18446744073709551616").is_err());
+        assert_eq!(
+            from_slice::<f64>(b"18446744073709551616").unwrap(),
+            2f64.powi(64)
+        );
+        assert_eq!(from_slice::<u64>(b"1e3").unwrap(), 1000);
+        assert!(from_slice::<f64>(b"-0").unwrap().is_sign_negative());
+        assert!(from_slice::<u64>(b"01").is_err());
+        assert!(from_slice::<i64>(b"-").is_err());
+    }
+
+    #[test]
+    fn deep() {
+        let nested = |depth: usize| "[".repeat(depth) + &"]".repeat(depth);
+        from_slice::<serde_json::Value>(nested(MAX_NESTING).as_bytes()).unwrap();
+        for json in [nested(MAX_NESTING + 1), "[".repeat(200_000)] {
+            let err = from_slice::<serde_json::Value>(json.as_bytes()).unwrap_err();
+            assert!(err.message().starts_with("Arrays and objects are nested"));
+        }
+    }
+}
@@ -0,0 +1,330 @@
+use hashbrown::hash_map;
+use serde::de::value::BorrowedStrDeserializer;
+use serde::de::{
+    self, DeserializeSeed, EnumAccess, MapAccess, SeqAccess, Unexpected, VariantAccess, Visitor,
+};
+use serde::{forward_to_deserialize_any, Deserialize, Deserializer};
+
+use super::{visit_number, DeError, KeyDeserializer};
+use crate::JsonValue;
+
+/// Deserializes a `T` out of an already parsed tree.
+///
+/// Strings are borrowed from the arena wherever `T` allows it, so the same
+/// document can be mapped into several types without parsing it again.
+pub fn from_value<'de, 'bump: 'de, T: Deserialize<'de>>(
+    value: &'de JsonValue<'bump>,
+) -> Result<T, DeError> {
+    T::deserialize(value)
+}
+
+impl<'bump> JsonValue<'bump> {
+    fn unexpected(&self) -> Unexpected<'_> {
+        match self {
+            JsonValue::Object(_) => Unexpected::Map,
+            JsonValue::Array(_) => Unexpected::Seq,
+            JsonValue::String(str) => Unexpected::Str(str),
+            JsonValue::Str(str) => Unexpected::Str(str),
+            JsonValue::Wtf8(bytes) => Unexpected::Bytes(bytes),
+            JsonValue::Number(num) => Unexpected::Float(*num),
+            JsonValue::Boolean(bool) => Unexpected::Bool(*bool),
+            JsonValue::Null => Unexpected::Unit,
+        }
+    }
+}
+
+impl<'de, 'bump: 'de> Deserializer<'de> for &'de JsonValue<'bump> {
+    type Error = DeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        match self {
+            JsonValue::Object(obj) => visitor.visit_map(ObjectAccess::new(obj)),
+            JsonValue::Array(arr) => visitor.visit_seq(ArrayAccess::new(arr)),
+            JsonValue::String(str) => visitor.visit_borrowed_str(str.as_str()),
+            JsonValue::Str(str) => visitor.visit_borrowed_str(str),
+            JsonValue::Wtf8(bytes) => visitor.visit_borrowed_bytes(bytes),
+            JsonValue::Number(num) => visit_number(*num, visitor),
+            JsonValue::Boolean(bool) => visitor.visit_bool(*bool),
+            JsonValue::Null => visitor.visit_unit(),
+        }
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        self.deserialize_f64(visitor)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        match self {
+            JsonValue::Number(num) => visitor.visit_f64(*num),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        match self {
+            JsonValue::String(str) => visitor.visit_borrowed_bytes(str.as_bytes()),
+            JsonValue::Str(str) => visitor.visit_borrowed_bytes(str.as_bytes()),
+            JsonValue::Wtf8(bytes) => visitor.visit_borrowed_bytes(bytes),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        match self {
+            JsonValue::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        match self {
+            JsonValue::String(str) => {
+                visitor.visit_enum(BorrowedStrDeserializer::<DeError>::new(str.as_str()))
+            }
+            JsonValue::Str(str) => visitor.visit_enum(BorrowedStrDeserializer::<DeError>::new(str)),
+            JsonValue::Object(obj) if obj.len() == 1 => {
+                let (variant, value) = obj.iter().next().unwrap();
+                visitor.visit_enum(EnumDeserializer { variant, value })
+            }
+            _ => Err(de::Error::invalid_type(
+                self.unexpected(),
+                &"a string or an object with a single key",
+            )),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 char str string
+        unit unit_struct seq tuple tuple_struct map struct identifier
+    }
+}
+
+struct ArrayAccess<'de, 'bump> {
+    iter: std::slice::Iter<'de, JsonValue<'bump>>,
+    index: usize,
+}
+
+impl<'de, 'bump> ArrayAccess<'de, 'bump> {
+    fn new(arr: &'de [JsonValue<'bump>]) -> Self {
+        Self {
+            iter: arr.iter(),
+            index: 0,
+        }
+    }
+}
+
+impl<'de, 'bump: 'de> SeqAccess<'de> for ArrayAccess<'de, 'bump> {
+    type Error = DeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, DeError> {
+        match self.iter.next() {
+            Some(value) => {
+                let index = self.index;
+                self.index += 1;
+                seed.deserialize(value)
+                    .map(Some)
+                    .map_err(|e| e.prepend(index))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct ObjectAccess<'de, 'bump> {
+    iter: hash_map::Iter<'de, &'bump str, JsonValue<'bump>>,
+    pending: Option<(&'de str, &'de JsonValue<'bump>)>,
+}
+
+impl<'de, 'bump> ObjectAccess<'de, 'bump> {
+    fn new(obj: &'de crate::JsonObject<'bump>) -> Self {
+        Self {
+            iter: obj.iter(),
+            pending: None,
+        }
+    }
+}
+
+impl<'de, 'bump: 'de> MapAccess<'de> for ObjectAccess<'de, 'bump> {
+    type Error = DeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, DeError> {
+        match self.iter.next() {
+            Some((key, value)) => {
+                self.pending = Some((*key, value));
+                seed.deserialize(KeyDeserializer::Borrowed(key))
+                    .map(Some)
+                    .map_err(|e| e.prepend(key))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, DeError> {
+        let (key, value) = self
+            .pending
+            .take()
+            .ok_or_else(|| <DeError as de::Error>::custom("value requested before key"))?;
+        seed.deserialize(value).map_err(|e| e.prepend(key))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct EnumDeserializer<'de, 'bump> {
+    variant: &'de str,
+    value: &'de JsonValue<'bump>,
+}
+
+impl<'de, 'bump: 'de> EnumAccess<'de> for EnumDeserializer<'de, 'bump> {
+    type Error = DeError;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), DeError> {
+        let variant = seed.deserialize(BorrowedStrDeserializer::<DeError>::new(self.variant))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de, 'bump: 'de> VariantAccess<'de> for EnumDeserializer<'de, 'bump> {
+    type Error = DeError;
+
+    fn unit_variant(self) -> Result<(), DeError> {
+        match self.value {
+            JsonValue::Null => Ok(()),
+            value => Err(de::Error::invalid_type(value.unexpected(), &"unit variant")),
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, DeError> {
+        seed.deserialize(self.value)
+            .map_err(|e| e.prepend(self.variant))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, DeError> {
+        self.value
+            .deserialize_seq(visitor)
+            .map_err(|e| e.prepend(self.variant))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        self.value
+            .deserialize_map(visitor)
+            .map_err(|e| e.prepend(self.variant))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bumpalo::Bump;
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::parse;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Item<'a> {
+        sku: &'a str,
+        count: u32,
+        price: Option<f64>,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    enum Shape {
+        Point,
+        Circle { radius: f64 },
+        Pair(i8, i8),
+    }
+
+    #[test]
+    fn typed_struct() {
+        let bump = Bump::new();
+        let json = br#"{"items":[{"sku":"a-1","count":3,"price":2.5},{"sku":"b\"2","count":0,"price":null}]}"#;
+        let val = parse(json, &bump).unwrap();
+        let items: HashMap<&str, Vec<Item>> = from_value(&val).unwrap();
+        assert_eq!(
+            items["items"],
+            [
+                Item {
+                    sku: "a-1",
+                    count: 3,
+                    price: Some(2.5)
+                },
+                Item {
+                    sku: "b\"2",
+                    count: 0,
+                    price: None
+                }
+            ]
+        );
+        let shapes: Vec<Shape> = from_value(
+            &parse(
+                br#"["Point",{"Circle":{"radius":1}},{"Pair":[1,-2]}]"#,
+                &bump,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            shapes,
+            [
+                Shape::Point,
+                Shape::Circle { radius: 1.0 },
+                Shape::Pair(1, -2)
+            ]
+        );
+    }
+
+    #[test]
+    fn error_path() {
+        let bump = Bump::new();
+        let val = parse(
+            br#"{"items":[{"sku":"a","count":1},{"sku":"b","count":-1}]}"#,
+            &bump,
+        )
+        .unwrap();
+        let err = from_value::<HashMap<&str, Vec<Item>>>(&val).unwrap_err();
+        assert_eq!(err.path(), "/items/1/count");
+        let val = parse(br#"{"a/b~":{"x":1.5}}"#, &bump).unwrap();
+        let err = from_value::<HashMap<&str, HashMap<&str, u8>>>(&val).unwrap_err();
+        assert_eq!(err.path(), "/a~1b~0/x");
+    }
+}
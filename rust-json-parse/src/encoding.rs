@@ -0,0 +1,287 @@
+//! Detecting UTF-16 and UTF-32 input and transcoding it to UTF-8.
+
+use bumpalo::collections::Vec;
+use bumpalo::Bump;
+
+use crate::{
+    parse_error, parse_with, JsonError, JsonValue, ParseContext, ParseError, ParseOptions,
+};
+use crate::{StringDecoding, Utf8Validation};
+
+pub(crate) const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// The Unicode encodings JSON can come in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Utf32Le,
+    Utf32Be,
+}
+
+impl Encoding {
+    /// Detects the encoding of `json` from its byte-order mark, returning it
+    /// with the length of the mark.
+    ///
+    /// Without a mark, it is inferred from where the first two characters,
+    /// which are always ASCII in JSON, have null bytes, as RFC 4627 describes.
+    pub fn detect(json: &[u8]) -> (Encoding, usize) {
+        match json {
+            [0x00, 0x00, 0xFE, 0xFF, ..] => (Encoding::Utf32Be, 4),
+            [0xFF, 0xFE, 0x00, 0x00, ..] => (Encoding::Utf32Le, 4),
+            [0xEF, 0xBB, 0xBF, ..] => (Encoding::Utf8, 3),
+            [0xFE, 0xFF, ..] => (Encoding::Utf16Be, 2),
+            [0xFF, 0xFE, ..] => (Encoding::Utf16Le, 2),
+            [0x00, 0x00, 0x00, _, ..] => (Encoding::Utf32Be, 0),
+            [_, 0x00, 0x00, 0x00, ..] => (Encoding::Utf32Le, 0),
+            [0x00, _, ..] => (Encoding::Utf16Be, 0),
+            [_, 0x00, ..] => (Encoding::Utf16Le, 0),
+            _ => (Encoding::Utf8, 0),
+        }
+    }
+
+    /// Bytes per code unit
+    fn unit(self) -> usize {
+        match self {
+            Encoding::Utf8 => 1,
+            Encoding::Utf16Le | Encoding::Utf16Be => 2,
+            Encoding::Utf32Le | Encoding::Utf32Be => 4,
+        }
+    }
+}
+
+/// Parses JSON in any of the `Encoding`s, detecting which with
+/// `Encoding::detect`.
+///
+/// UTF-16 and UTF-32 input is transcoded to UTF-8 in the arena first, and a
+/// byte-order mark is always skipped. Unpaired surrogates and invalid code
+/// points are replaced with U+FFFD under `StringDecoding::Replace`, and are
+/// errors otherwise.
+pub fn parse_any_encoding<'bump>(
+    json_buf: &[u8],
+    allocator: &'bump Bump,
+    options: &ParseOptions,
+) -> Result<JsonValue<'bump>, JsonError> {
+    let (encoding, bom) = Encoding::detect(json_buf);
+    let mut ctx = ParseContext {
+        options: *options,
+        ..Default::default()
+    };
+    if encoding == Encoding::Utf8 {
+        ctx.options.skip_bom = true;
+        return parse_with(json_buf, allocator, ctx);
+    }
+    let utf8 = transcode(&json_buf[bom..], encoding, bom, allocator, options.strings)?;
+    // Transcoding only ever writes valid UTF-8
+    ctx.options.utf8 = Utf8Validation::PerString;
+    ctx.utf8_validated = true;
+    parse_with(&utf8, allocator, ctx)
+}
+
+/// Transcodes `json`, which starts `bom` bytes into the original input
+fn transcode<'bump>(
+    json: &[u8],
+    encoding: Encoding,
+    bom: usize,
+    allocator: &'bump Bump,
+    strings: StringDecoding,
+) -> Result<Vec<'bump, u8>, JsonError> {
+    let unit = encoding.unit();
+    let mut utf8 = Vec::with_capacity_in(json.len() / unit, allocator);
+    let mut push = |char: char| {
+        utf8.extend_from_slice(char.encode_utf8(&mut [0; 4]).as_bytes());
+    };
+    let units = json.chunks_exact(unit);
+    let error = |index: usize| {
+        let offset = index * unit;
+        parse_error(
+            ParseError::InvalidEncoding {
+                encoding,
+                offset: bom + offset,
+            },
+            &json[offset..],
+        )
+    };
+    if !units.remainder().is_empty() {
+        return Err(error(json.len() / unit));
+    }
+    match encoding {
+        Encoding::Utf16Le | Encoding::Utf16Be => {
+            let units = units.map(|unit| match encoding {
+                Encoding::Utf16Le => u16::from_le_bytes([unit[0], unit[1]]),
+                _ => u16::from_be_bytes([unit[0], unit[1]]),
+            });
+            let mut index = 0;
+            for char in char::decode_utf16(units) {
+                match char {
+                    Ok(char) => {
+                        push(char);
+                        index += char.len_utf16();
+                    }
+                    Err(_) if strings == StringDecoding::Replace => {
+                        push(char::REPLACEMENT_CHARACTER);
+                        index += 1;
+                    }
+                    Err(_) => return Err(error(index)),
+                }
+            }
+        }
+        Encoding::Utf32Le | Encoding::Utf32Be => {
+            for (index, unit) in units.enumerate() {
+                let unit = [unit[0], unit[1], unit[2], unit[3]];
+                let code = match encoding {
+                    Encoding::Utf32Le => u32::from_le_bytes(unit),
+                    _ => u32::from_be_bytes(unit),
+                };
+                match char::from_u32(code) {
+                    Some(char) => push(char),
+                    None if strings == StringDecoding::Replace => push(char::REPLACEMENT_CHARACTER),
+                    None => return Err(error(index)),
+                }
+            }
+        }
+        Encoding::Utf8 => unreachable!(),
+    }
+    Ok(utf8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, Backend};
+
+    fn encode(json: &str, encoding: Encoding, bom: bool) -> std::vec::Vec<u8> {
+        let chars = bom.then_some('\u{FEFF}').into_iter().chain(json.chars());
+        match encoding {
+            Encoding::Utf8 => chars.collect::<std::string::String>().into_bytes(),
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let units: std::vec::Vec<u16> = chars
+                    .flat_map(|char| char.encode_utf16(&mut [0; 2]).to_vec())
+                    .collect();
+                units
+                    .into_iter()
+                    .flat_map(|unit| match encoding {
+                        Encoding::Utf16Le => unit.to_le_bytes(),
+                        _ => unit.to_be_bytes(),
+                    })
+                    .collect()
+            }
+            Encoding::Utf32Le | Encoding::Utf32Be => chars
+                .flat_map(|char| match encoding {
+                    Encoding::Utf32Le => (char as u32).to_le_bytes(),
+                    _ => (char as u32).to_be_bytes(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn detect_and_transcode() {
+        let bump = Bump::new();
+        let options = ParseOptions::default();
+        for json in [r#"{"a": ["é", "😀", 1.5, null]}"#, "1", "[]", r#""ab""#] {
+            let expected = parse(json.as_bytes(), &bump).unwrap();
+            for encoding in [
+                Encoding::Utf8,
+                Encoding::Utf16Le,
+                Encoding::Utf16Be,
+                Encoding::Utf32Le,
+                Encoding::Utf32Be,
+            ] {
+                for bom in [false, true] {
+                    let bytes = encode(json, encoding, bom);
+                    let bom_len = if bom {
+                        encode("", encoding, true).len()
+                    } else {
+                        0
+                    };
+                    assert_eq!(Encoding::detect(&bytes), (encoding, bom_len), "{json}");
+                    let ret = parse_any_encoding(&bytes, &bump, &options).unwrap();
+                    assert_eq!(ret, expected, "{json} {encoding:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn utf8_bom() {
+        let bump = Bump::new();
+        let json = b"\xEF\xBB\xBF[1]";
+        assert!(parse(json, &bump).is_err());
+        let options = ParseOptions {
+            skip_bom: true,
+            ..Default::default()
+        };
+        for backend in [Backend::Streaming, Backend::Structural] {
+            let options = ParseOptions { backend, ..options };
+            let ret = crate::parse_with_options(json, &bump, &options).unwrap();
+            assert_eq!(ret, parse(b"[1]", &bump).unwrap());
+        }
+    }
+
+    #[test]
+    fn invalid_input() {
+        let bump = Bump::new();
+        let lone = b"[\x00\"\x00\x00\xD8\"\x00]\x00";
+        assert!(matches!(
+            parse_any_encoding(lone, &bump, &ParseOptions::default()),
+            Err(JsonError::ParseError(
+                ParseError::InvalidEncoding {
+                    encoding: Encoding::Utf16Le,
+                    offset: 4
+                },
+                _
+            ))
+        ));
+        let options = ParseOptions {
+            strings: StringDecoding::Replace,
+            ..Default::default()
+        };
+        let ret = parse_any_encoding(lone, &bump, &options).unwrap();
+        match ret {
+            JsonValue::Array(arr) => assert_eq!(arr[0].as_str(), Some("\u{FFFD}")),
+            other => panic!("{other:?}"),
+        }
+        // Offsets count the byte-order mark
+        let lone_after_bom = [&b"\xFF\xFE"[..], lone].concat();
+        assert!(matches!(
+            parse_any_encoding(&lone_after_bom, &bump, &ParseOptions::default()),
+            Err(JsonError::ParseError(
+                ParseError::InvalidEncoding {
+                    encoding: Encoding::Utf16Le,
+                    offset: 6
+                },
+                _
+            ))
+        ));
+        let invalid_utf8 = b"\xEF\xBB\xBF[\"\xFF\"]";
+        let options = ParseOptions {
+            utf8: Utf8Validation::WholeInput,
+            skip_bom: true,
+            ..Default::default()
+        };
+        for ret in [
+            crate::parse_with_options(invalid_utf8, &bump, &options),
+            parse_any_encoding(invalid_utf8, &bump, &options),
+        ] {
+            assert!(matches!(
+                ret,
+                Err(JsonError::ParseError(
+                    ParseError::InvalidUtf8At { offset: 5 },
+                    _
+                ))
+            ));
+        }
+        assert!(matches!(
+            parse_any_encoding(b"[\x00]", &bump, &ParseOptions::default()),
+            Err(JsonError::ParseError(
+                ParseError::InvalidEncoding {
+                    encoding: Encoding::Utf16Le,
+                    offset: 2
+                },
+                _
+            ))
+        ));
+    }
+}
@@ -0,0 +1,272 @@
+//! On-demand access to large documents, parsing only the parts that are used.
+
+use std::cell::RefCell;
+use std::mem;
+
+use bumpalo::Bump;
+use hashbrown::HashMap;
+
+use crate::parsers::skip::skip_value;
+use crate::parsers::string::{read_borrowed_str, read_string};
+use crate::slice_iter::{CopyIter, SliceIter};
+use crate::{
+    ignore_ws, parse_error, parse_next, JsonError, JsonValue, ParseContext, ParseError, ParseState,
+};
+
+/// A document that is only parsed as it is accessed.
+///
+/// Looking up a key or index skips over the values before it with a
+/// string-aware bracket-matching scan, remembering where each skipped
+/// container ended so it is never scanned twice. A value is only parsed into
+/// a `JsonValue` when `LazyValue::value` is called, and is then cached.
+pub struct LazyDocument<'a> {
+    json: &'a [u8],
+    allocator: Bump,
+    /// End offsets of the containers skipped over so far, by start offset
+    skipped: RefCell<HashMap<usize, usize>>,
+    /// Values parsed so far, by start offset
+    parsed: RefCell<HashMap<usize, &'static JsonValue<'static>>>,
+    /// Reset for each key with escapes that `LazyValue::get` compares
+    scratch: RefCell<Bump>,
+}
+
+/// A not yet parsed value somewhere in a `LazyDocument`
+#[derive(Clone, Copy)]
+pub struct LazyValue<'d, 'a> {
+    doc: &'d LazyDocument<'a>,
+    start: usize,
+}
+
+impl<'a> LazyDocument<'a> {
+    pub fn new(json: &'a [u8]) -> Self {
+        Self {
+            json,
+            allocator: Bump::new(),
+            skipped: RefCell::new(HashMap::new()),
+            parsed: RefCell::new(HashMap::new()),
+            scratch: RefCell::new(Bump::new()),
+        }
+    }
+
+    pub fn root(&self) -> LazyValue<'_, 'a> {
+        let mut json = self.iter_at(0);
+        ignore_ws(&mut json);
+        LazyValue {
+            doc: self,
+            start: json.index(),
+        }
+    }
+
+    pub fn bump(&self) -> &Bump {
+        &self.allocator
+    }
+
+    fn iter_at(&self, offset: usize) -> SliceIter<'a, u8> {
+        let mut json = SliceIter::new(self.json);
+        json.set_index(offset);
+        json
+    }
+
+    fn error(&self, e: ParseError, offset: usize) -> JsonError {
+        parse_error(e, &self.json[offset..])
+    }
+
+    /// Skips the value at the iterator, jumping straight to its end if it was
+    /// skipped before
+    fn skip(&self, json: &mut SliceIter<'a, u8>) -> Result<(), JsonError> {
+        let start = json.index();
+        if let Some(end) = self.skipped.borrow().get(&start) {
+            json.set_index(*end);
+            return Ok(());
+        }
+        skip_value(json).map_err(|e| self.error(e, json.index()))?;
+        if matches!(self.json[start], b'[' | b'{') {
+            self.skipped.borrow_mut().insert(start, json.index());
+        }
+        Ok(())
+    }
+
+    fn expect(&self, json: &mut SliceIter<'a, u8>, byte: u8) -> Result<(), JsonError> {
+        ignore_ws(json);
+        let offset = json.index();
+        match json.next() {
+            Some(b) if b == byte => Ok(()),
+            found => {
+                let found = found.map(|b| b as char);
+                let e = match byte {
+                    b'{' | b'[' => ParseError::ExpectedNextValue { found },
+                    b':' => ParseError::ExpectedColon { found },
+                    _ => unreachable!(),
+                };
+                Err(self.error(e, offset))
+            }
+        }
+    }
+}
+
+impl<'d, 'a> LazyValue<'d, 'a> {
+    /// Finds the value of `key`, if this is an object that has it
+    pub fn get(&self, key: &str) -> Result<Option<LazyValue<'d, 'a>>, JsonError> {
+        let doc = self.doc;
+        let mut json = doc.iter_at(self.start);
+        doc.expect(&mut json, b'{')?;
+        ignore_ws(&mut json);
+        if json.peek_copy() == Some(b'}') {
+            return Ok(None);
+        }
+        loop {
+            ignore_ws(&mut json);
+            let offset = json.index();
+            let matched = match read_borrowed_str(&mut json) {
+                Ok(Some(str)) => Ok(str == key),
+                Ok(None) => {
+                    let mut scratch = doc.scratch.borrow_mut();
+                    scratch.reset();
+                    read_string(&mut json, &scratch).map(|str| str == key)
+                }
+                Err(e) => Err(e),
+            };
+            if matched.map_err(|e| doc.error(e, offset))? {
+                doc.expect(&mut json, b':')?;
+                ignore_ws(&mut json);
+                return Ok(Some(LazyValue {
+                    doc,
+                    start: json.index(),
+                }));
+            }
+            doc.expect(&mut json, b':')?;
+            ignore_ws(&mut json);
+            doc.skip(&mut json)?;
+            ignore_ws(&mut json);
+            let offset = json.index();
+            match json.next() {
+                Some(b',') => continue,
+                Some(b'}') => return Ok(None),
+                found => {
+                    return Err(doc.error(
+                        ParseError::ExpectedEndOfObject {
+                            found: found.map(|b| b as char),
+                        },
+                        offset,
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Finds the element at `index`, if this is an array that long
+    pub fn at(&self, index: usize) -> Result<Option<LazyValue<'d, 'a>>, JsonError> {
+        let doc = self.doc;
+        let mut json = doc.iter_at(self.start);
+        doc.expect(&mut json, b'[')?;
+        ignore_ws(&mut json);
+        if json.peek_copy() == Some(b']') {
+            return Ok(None);
+        }
+        for _ in 0..index {
+            doc.skip(&mut json)?;
+            ignore_ws(&mut json);
+            let offset = json.index();
+            match json.next() {
+                Some(b',') => ignore_ws(&mut json),
+                Some(b']') => return Ok(None),
+                found => {
+                    return Err(doc.error(
+                        ParseError::ExpectedEndOfArray {
+                            found: found.map(|b| b as char),
+                        },
+                        offset,
+                    ))
+                }
+            }
+        }
+        Ok(Some(LazyValue {
+            doc,
+            start: json.index(),
+        }))
+    }
+
+    /// The unparsed text of this value
+    pub fn raw(&self) -> Result<&'a [u8], JsonError> {
+        let mut json = self.doc.iter_at(self.start);
+        self.doc.skip(&mut json)?;
+        Ok(&self.doc.json[self.start..json.index()])
+    }
+
+    /// Parses this value, or returns it from the cache if it was parsed before
+    pub fn value(&self) -> Result<&'d JsonValue<'d>, JsonError> {
+        let doc = self.doc;
+        if let Some(value) = doc.parsed.borrow().get(&self.start) {
+            return Ok(unsafe { mem::transmute::<&JsonValue, &JsonValue>(*value) });
+        }
+        let mut json = doc.iter_at(self.start);
+        let value = parse_next(
+            &mut json,
+            &doc.allocator,
+            ParseState::Value,
+            ParseContext::default(),
+        )
+        .map_err(|e| doc.error(e, json.index()))?;
+        let value: &'d JsonValue<'d> = doc.allocator.alloc(value);
+        doc.parsed
+            .borrow_mut()
+            .insert(self.start, unsafe { mem::transmute(value) });
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn lazy_access() {
+        let mut json =
+            StdString::from(r#"{"first": 1, "skipped": ["]\"}", {"b": "\\", "c": [[{}]]}], "#);
+        json.push_str(r#""big": ["#);
+        json.push_str(&"[1, {\"a\": \"}\"}], ".repeat(1000));
+        json.push_str(r#"null], "last": {"x": "y", "z\n": [true, false]}, "#);
+        json.push_str(&format!("\"{}\\n\": 0}}", "long key ".repeat(100)));
+        let doc = LazyDocument::new(json.as_bytes());
+        let root = doc.root();
+        assert_eq!(
+            root.get("first").unwrap().unwrap().value().unwrap(),
+            &JsonValue::Number(1.0)
+        );
+        assert!(root.get("missing").unwrap().is_none());
+
+        let last = root.get("last").unwrap().unwrap();
+        let z = last.get("z\n").unwrap().unwrap();
+        assert_eq!(
+            z.at(1).unwrap().unwrap().value().unwrap(),
+            &JsonValue::Boolean(false)
+        );
+        assert!(z.at(2).unwrap().is_none());
+        assert_eq!(last.get("x").unwrap().unwrap().raw().unwrap(), b"\"y\"");
+        // Escaped keys are compared without growing the document's arena
+        let allocated = doc.bump().allocated_bytes();
+        for _ in 0..1000 {
+            assert!(root.get("skipped\n").unwrap().is_none());
+        }
+        assert_eq!(doc.bump().allocated_bytes(), allocated);
+
+        let big = root.get("big").unwrap().unwrap();
+        assert_eq!(
+            big.at(1000).unwrap().unwrap().value().unwrap(),
+            &JsonValue::Null
+        );
+        let bump = Bump::new();
+        assert_eq!(
+            root.value().unwrap(),
+            &parse(json.as_bytes(), &bump).unwrap()
+        );
+        // Served from the cache the second time
+        assert!(std::ptr::eq(root.value().unwrap(), root.value().unwrap()));
+
+        assert!(root.at(0).is_err());
+        assert!(LazyDocument::new(b"{\"a\": [1, 2").root().get("b").is_err());
+    }
+
+    use std::string::String as StdString;
+}
@@ -1,34 +1,106 @@
-#![feature(portable_simd)]
+#![cfg_attr(feature = "portable_simd", feature(portable_simd))]
+// AVX-512 intrinsics are only stable from Rust 1.89, see build.rs
+#![cfg_attr(
+    all(feature = "avx512", avx512_unstable),
+    feature(stdsimd, avx512_target_feature)
+)]
 
 use std::mem;
 use std::string::String as StdString;
-use std::vec::Vec as StdVec;
 
 use bumpalo::collections::{String, Vec};
 use bumpalo::Bump;
 use hashbrown::hash_map::DefaultHashBuilder;
 use hashbrown::{BumpWrapper, HashMap};
-use parsers::{number::read_number, string::read_string};
+use parsers::json5::{self, read_identifier};
+use parsers::number::{read_json5_number, read_number};
+use parsers::string::{read_borrowed_str_with, read_key_str, read_string_with};
 use slice_iter::{CopyIter, SliceIter};
 
+pub use cst::{Container, Cst, CstError, Node};
+#[cfg(feature = "serde")]
+pub use de::{from_slice, from_value, DeError};
+pub use encoding::{parse_any_encoding, Encoding};
+pub use lazy::{LazyDocument, LazyValue};
+pub use parsers::skip::value_range;
+pub use partial::{parse_partial, Partial};
+pub use recover::{parse_recovering, Diagnostic, Recovered};
+pub use repair::{repair, Repair, RepairKind, Repaired};
+pub use select::{Selector, SelectorError};
+#[cfg(feature = "serde")]
+pub use ser::{to_value_in, SerError};
+pub use simd::{set_simd_kernel, simd_kernel, simd_kernel_env_error, SimdKernel, SimdKernelError};
+pub use spans::{parse_with_spans, SpanMap};
+pub use tape::{Elements, Members, Tape, TapeRef};
+pub use validate::validate;
+
+mod cst;
+#[cfg(feature = "serde")]
+mod de;
+mod encoding;
+mod lazy;
 mod parsers;
+mod partial;
+mod recover;
+mod repair;
+mod select;
+#[cfg(feature = "serde")]
+mod ser;
+mod simd;
 mod slice_iter;
+mod spans;
+mod structural;
+mod tape;
+mod validate;
 
+/// Keys are `&str`s in the arena, or borrowed from the input by
+/// `parse_borrowed`
 pub type JsonObject<'bump> =
-    HashMap<String<'bump>, JsonValue<'bump>, DefaultHashBuilder, BumpWrapper<'bump>>;
+    HashMap<&'bump str, JsonValue<'bump>, DefaultHashBuilder, BumpWrapper<'bump>>;
 
 pub type JsonResult<T> = Result<T, ParseError>;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 pub enum JsonValue<'bump> {
     Object(&'bump mut JsonObject<'bump>),
     Array(&'bump mut Vec<'bump, JsonValue<'bump>>),
     String(&'bump mut String<'bump>),
+    /// A string borrowed from the input, only produced by `parse_borrowed`
+    Str(&'bump mut &'bump str),
+    /// A string with lone surrogates kept as WTF-8, only produced with
+    /// `StringDecoding::Wtf8`
+    Wtf8(&'bump mut Vec<'bump, u8>),
     Number(f64),
     Boolean(bool),
     Null,
 }
 
+impl<'bump> JsonValue<'bump> {
+    /// The contents of either kind of string
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(str) => Some(str.as_str()),
+            JsonValue::Str(str) => Some(str),
+            _ => None,
+        }
+    }
+}
+
+/// Strings compare by their contents, whether they were copied or borrowed.
+impl<'bump> PartialEq for JsonValue<'bump> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (JsonValue::Object(a), JsonValue::Object(b)) => a == b,
+            (JsonValue::Array(a), JsonValue::Array(b)) => a == b,
+            (JsonValue::Number(a), JsonValue::Number(b)) => a == b,
+            (JsonValue::Boolean(a), JsonValue::Boolean(b)) => a == b,
+            (JsonValue::Null, JsonValue::Null) => true,
+            (JsonValue::Wtf8(a), JsonValue::Wtf8(b)) => a == b,
+            (a, b) => a.as_str().is_some() && a.as_str() == b.as_str(),
+        }
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum JsonError {
     #[error("File too long: len {len} longer than maximum allowed of 500 MiB")]
@@ -47,16 +119,65 @@ pub enum ParseError {
     ExpectedNextValue { found: Option<char> },
     #[error("Expect colon after object key, found {found:?}")]
     ExpectedColon { found: Option<char> },
+    /// No longer returned, number errors are `InvalidNumber`
+    #[deprecated(note = "number errors are now `ParseError::InvalidNumber`")]
     #[error("Invalid number literal")]
     InvalidNumberLiteral,
+    #[error("Invalid number literal: {0}")]
+    InvalidNumber(NumberError),
+    #[error("Control character {found:?} must be escaped in a string")]
+    ControlCharacter { found: char },
     #[error("Invalid string escape")]
     InvalidStringEscape,
+    #[error("Unpaired surrogate {code:#06x} in a string escape")]
+    LoneSurrogate { code: u16 },
     #[error("JSON string was not valid UTF-8: {string}")]
     InvalidUtf8 { string: StdString },
+    /// From `Utf8Validation::WholeInput`, `offset` is where the invalid
+    /// sequence starts in the input
+    #[error("Invalid UTF-8 sequence at byte {offset}")]
+    InvalidUtf8At { offset: usize },
+    /// From `parse_any_encoding`, `offset` is in the original input
+    #[error("Invalid {encoding:?} at byte {offset}")]
+    InvalidEncoding { encoding: Encoding, offset: usize },
     #[error("String must start with a quote `\"`, found {found:?}")]
     StringQuote { found: Option<char> },
     #[error("Expected the next value, found the end of the file")]
     UnexpectedEndOfFile,
+    #[error("Comment was never closed with `*/`")]
+    UnterminatedComment,
+    #[error("Expected an object key, found {found:?}")]
+    InvalidIdentifier { found: Option<char> },
+    #[error("Unexpected {found:?} after the value")]
+    TrailingCharacters { found: char },
+    /// From the parsers that recurse into each array and object
+    #[error("Arrays and objects are nested more than {limit} deep")]
+    NestingTooDeep { limit: usize },
+}
+
+/// How deeply the parsers that recurse into each array and object, like
+/// `parse_recovering` and `from_slice`, nest them before giving up, so that
+/// they can't overflow the stack
+pub(crate) const MAX_NESTING: usize = 512;
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumberError {
+    #[error("leading zero")]
+    LeadingZero,
+    #[error("missing digits")]
+    MissingDigits,
+    #[error("missing fraction digits")]
+    MissingFractionDigits,
+    #[error("missing exponent digits")]
+    MissingExponentDigits,
+    #[error("too large for an f64")]
+    OutOfRange,
+}
+
+impl From<NumberError> for ParseError {
+    fn from(e: NumberError) -> Self {
+        ParseError::InvalidNumber(e)
+    }
 }
 
 enum ParseState {
@@ -115,6 +236,161 @@ impl JsonDocument {
 pub fn parse<'a, 'bump>(
     json_buf: &'a [u8],
     allocator: &'bump Bump,
+) -> Result<JsonValue<'bump>, JsonError> {
+    parse_with(json_buf, allocator, ParseContext::default())
+}
+
+/// Parses like `parse`, but strings without escapes borrow from `json_buf` as
+/// `JsonValue::Str` instead of being copied into the arena, and so do object
+/// keys without escapes.
+pub fn parse_borrowed<'bump>(
+    json_buf: &'bump [u8],
+    allocator: &'bump Bump,
+) -> Result<JsonValue<'bump>, JsonError> {
+    parse_borrowed_with_options(json_buf, allocator, &ParseOptions::default())
+}
+
+pub fn parse_with_options<'bump>(
+    json_buf: &[u8],
+    allocator: &'bump Bump,
+    options: &ParseOptions,
+) -> Result<JsonValue<'bump>, JsonError> {
+    parse_with(
+        json_buf,
+        allocator,
+        ParseContext {
+            options: *options,
+            ..Default::default()
+        },
+    )
+}
+
+pub fn parse_borrowed_with_options<'bump>(
+    json_buf: &'bump [u8],
+    allocator: &'bump Bump,
+    options: &ParseOptions,
+) -> Result<JsonValue<'bump>, JsonError> {
+    parse_with(
+        json_buf,
+        allocator,
+        ParseContext {
+            options: *options,
+            borrow_strings: true,
+            ..Default::default()
+        },
+    )
+}
+
+/// Which parser `parse_with_options` runs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// Walks the input byte by byte, using SIMD only to scan through strings
+    #[default]
+    Streaming,
+    /// First indexes every structural character with SIMD, 64 bytes at a
+    /// time, then builds the tree from that index without recursion.
+    /// Experimental, and slower than `Streaming` for now
+    Structural,
+}
+
+/// When the parser checks that strings are UTF-8
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Utf8Validation {
+    /// Each string as it is read
+    #[default]
+    PerString,
+    /// The entire input once before parsing, which reports the exact offset
+    /// of an invalid sequence, even outside a string
+    WholeInput,
+}
+
+/// What to do with strings that aren't valid Unicode: invalid UTF-8 bytes, or
+/// `\u` escapes of lone UTF-16 surrogates like `"\uD800"`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StringDecoding {
+    /// Fail with `ParseError::InvalidUtf8` or `ParseError::LoneSurrogate`
+    #[default]
+    Reject,
+    /// Replace each invalid sequence or lone surrogate with U+FFFD
+    Replace,
+    /// Keep lone surrogates as WTF-8 in a `JsonValue::Wtf8`, so the string can
+    /// be written back out exactly. Invalid UTF-8 is still rejected, and so
+    /// are lone surrogates in object keys
+    Wtf8,
+}
+
+/// Which grammar the input follows
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dialect {
+    #[default]
+    Json,
+    /// [JSON5](https://spec.json5.org/), which adds comments, trailing commas,
+    /// single quoted and multi-line strings, unquoted keys, hexadecimal
+    /// numbers, `Infinity` and `NaN`. Always parsed by `Backend::Streaming`,
+    /// which unlike with JSON checks that nothing but whitespace and comments
+    /// follows the value
+    Json5,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ParseOptions {
+    pub backend: Backend,
+    pub dialect: Dialect,
+    pub utf8: Utf8Validation,
+    pub strings: StringDecoding,
+    /// Skip a leading UTF-8 byte-order mark instead of failing on it
+    pub skip_bom: bool,
+    /// Allow `//` and `/* */` comments wherever whitespace is, as in VS
+    /// Code's JSONC settings files
+    pub comments: bool,
+    /// Allow a comma after the last element of an array or object.
+    ///
+    /// Like `comments`, this is only supported by `Backend::Streaming`, which
+    /// is used instead of `Backend::Structural` when either is set.
+    pub trailing_commas: bool,
+    /// Fail with `NumberError::OutOfRange` on numbers too large for an
+    /// `f64`, like `1e400`, instead of reading them as infinity. Under
+    /// `Dialect::Json5` they are infinity either way, as in JavaScript.
+    pub reject_out_of_range: bool,
+}
+
+/// Settings threaded through `parse_next`
+#[derive(Clone, Copy, Default)]
+pub(crate) struct ParseContext {
+    options: ParseOptions,
+    /// Only set by `parse_borrowed`, which ties the input to the arena's lifetime
+    borrow_strings: bool,
+    /// Set once the whole input has been validated, so strings skip it
+    utf8_validated: bool,
+}
+
+impl ParseContext {
+    /// Whether the input needs anything beyond strict JSON from the parser
+    fn lenient(&self) -> bool {
+        self.options.dialect != Dialect::Json || self.comments() || self.trailing_commas()
+    }
+
+    fn comments(&self) -> bool {
+        self.options.comments || self.options.dialect == Dialect::Json5
+    }
+
+    fn trailing_commas(&self) -> bool {
+        self.options.trailing_commas || self.options.dialect == Dialect::Json5
+    }
+
+    /// Applies `reject_out_of_range` to a number from `read_number`
+    fn number(&self, num: f64) -> JsonResult<f64> {
+        if self.options.reject_out_of_range && num.is_infinite() {
+            return Err(NumberError::OutOfRange.into());
+        }
+        Ok(num)
+    }
+}
+
+fn parse_with<'bump>(
+    json_buf: &[u8],
+    allocator: &'bump Bump,
+    mut ctx: ParseContext,
 ) -> Result<JsonValue<'bump>, JsonError> {
     if json_buf.len() >= 0x20000000 {
         return Err(JsonError::FileTooLong {
@@ -122,80 +398,119 @@ pub fn parse<'a, 'bump>(
         });
     }
 
-    let mut json = SliceIter::new(json_buf);
-    ignore_ws(&mut json);
+    let (json_buf, bom) = match json_buf.strip_prefix(encoding::UTF8_BOM) {
+        Some(json_buf) if ctx.options.skip_bom => (json_buf, encoding::UTF8_BOM.len()),
+        _ => (json_buf, 0),
+    };
 
-    match parse_next(&mut json, allocator, ParseState::Value) {
-        Ok(v) => return Ok(v),
-        Err(e) => {
-            let remaining = StdString::from_utf8_lossy(&json.collect::<StdVec<u8>>()).to_string();
-            return Err(JsonError::ParseError(
-                e,
-                if remaining.len() >= 500 {
-                    format!("{}…", &remaining[0..500])
-                } else {
-                    remaining
+    if ctx.options.utf8 == Utf8Validation::WholeInput {
+        if let Err(e) = simdutf8::compat::from_utf8(json_buf) {
+            let valid = e.valid_up_to();
+            return Err(parse_error(
+                ParseError::InvalidUtf8At {
+                    offset: bom + valid,
                 },
+                &json_buf[valid..],
             ));
         }
+        ctx.utf8_validated = true;
+    }
+
+    if ctx.options.backend == Backend::Structural && !ctx.lenient() {
+        let indices = structural::index(json_buf)
+            .map_err(|(e, offset)| parse_error(e, &json_buf[offset..]))?;
+        let mut builder = structural::Builder::new(json_buf, &indices, allocator, ctx);
+        return builder
+            .build()
+            .map_err(|e| parse_error(e, &json_buf[builder.offset()..]));
+    }
+
+    let mut json = SliceIter::new(json_buf);
+    if ctx.options.dialect == Dialect::Json5 {
+        return parse_json5_root(&mut json, allocator, ctx)
+            .map_err(|e| parse_error(e, &json_buf[json.index()..]));
+    }
+    ignore_ws(&mut json);
+
+    match parse_next(&mut json, allocator, ParseState::Value, ctx) {
+        Ok(v) => return Ok(v),
+        Err(e) => return Err(parse_error(e, &json_buf[json.index()..])),
     };
 }
 
+/// Parses a whole JSON5 document, which has to be exactly one value
+fn parse_json5_root<'bump>(
+    json: &mut SliceIter<'_, u8>,
+    alloc: &'bump Bump,
+    ctx: ParseContext,
+) -> JsonResult<JsonValue<'bump>> {
+    skip_ws(json, ctx)?;
+    if json.peek_copy().is_none() {
+        return Err(ParseError::UnexpectedEndOfFile);
+    }
+    let value = parse_next(json, alloc, ParseState::Value, ctx)?;
+    skip_ws(json, ctx)?;
+    if let Some(found) = json.peek_copy() {
+        return Err(ParseError::TrailingCharacters {
+            found: found as char,
+        });
+    }
+    Ok(value)
+}
+
+/// Attaches the start of the unparsed input to a parse error
+fn parse_error(e: ParseError, remaining: &[u8]) -> JsonError {
+    let remaining = StdString::from_utf8_lossy(remaining).to_string();
+    JsonError::ParseError(
+        e,
+        if remaining.len() >= 500 {
+            format!("{}…", &remaining[0..500])
+        } else {
+            remaining
+        },
+    )
+}
+
 fn parse_next<'a, 'bump>(
     json: &mut SliceIter<'a, u8>,
     alloc: &'bump Bump,
     state: ParseState,
+    ctx: ParseContext,
 ) -> JsonResult<JsonValue<'bump>> {
-    ignore_ws(json);
+    skip_ws(json, ctx)?;
     if let Some(char) = json.peek_copy() {
         match state {
             ParseState::Value => {
-                if is_string(char) {
-                    return Ok(JsonValue::String(alloc.alloc(read_string(json, alloc)?)));
-                }
-                if is_number(char) {
-                    return Ok(JsonValue::Number(read_number(json)?));
-                }
                 if is_array(char) {
                     json.ignore_next();
-                    return parse_next(json, alloc, ParseState::Array);
+                    return parse_next(json, alloc, ParseState::Array, ctx);
                 }
                 if is_object(char) {
                     json.ignore_next();
-                    return parse_next(json, alloc, ParseState::Object);
+                    return parse_next(json, alloc, ParseState::Object, ctx);
                 }
-                let next_4: [u8; 4] = json
-                    .peek_many()
-                    .map_or(Err(ParseError::UnexpectedEndOfFile), |v| Ok(v))?;
-                if &next_4 == b"true" {
-                    json.ignore_many(4);
-                    return Ok(JsonValue::Boolean(true));
-                }
-                if &next_4 == b"null" {
-                    json.ignore_many(4);
-                    return Ok(JsonValue::Null);
-                }
-                if json.peek_many_ref(5) == Some(b"false") {
-                    json.ignore_many(5);
-                    return Ok(JsonValue::Boolean(false));
-                }
-                return Err(ParseError::ExpectedNextValue {
-                    found: json.next().map(|i| i as char),
-                });
+                return parse_scalar(json, alloc, char, ctx);
             }
             ParseState::Array => {
                 let mut contents = Vec::new_in(alloc);
                 if b']' == char {
                     _ = json.next().unwrap();
-                    return Ok(JsonValue::Array(alloc.alloc(contents)));
+                    return Ok(array_value(alloc, contents));
                 }
                 loop {
-                    ignore_ws(json);
-                    contents.push(parse_next(json, alloc, ParseState::Value)?);
-                    ignore_ws(json);
+                    skip_ws(json, ctx)?;
+                    contents.push(parse_next(json, alloc, ParseState::Value, ctx)?);
+                    skip_ws(json, ctx)?;
 
                     match json.next() {
                         Some(b']') => break,
+                        Some(b',') if ctx.trailing_commas() => {
+                            skip_ws(json, ctx)?;
+                            if json.peek_copy() == Some(b']') {
+                                json.ignore_next();
+                                break;
+                            }
+                        }
                         Some(b',') => continue,
                         v => {
                             return Err(ParseError::ExpectedEndOfArray {
@@ -205,31 +520,37 @@ fn parse_next<'a, 'bump>(
                     }
                 }
                 contents.shrink_to_fit();
-                return Ok(JsonValue::Array(alloc.alloc(contents)));
+                return Ok(array_value(alloc, contents));
             }
             ParseState::Object => {
-                let mut contents: HashMap<String<'bump>, JsonValue<'bump>, _, BumpWrapper> =
-                    HashMap::new_in(BumpWrapper(alloc));
+                let mut contents: JsonObject<'bump> = HashMap::new_in(BumpWrapper(alloc));
                 if char == b'}' {
                     _ = json.next().unwrap();
-                    return Ok(JsonValue::Object(alloc.alloc(contents)));
+                    return Ok(object_value(alloc, contents));
                 }
                 loop {
-                    ignore_ws(json);
-                    let key = read_string(json, alloc)?;
-                    ignore_ws(json);
+                    skip_ws(json, ctx)?;
+                    let key = parse_key(json, alloc, ctx)?;
+                    skip_ws(json, ctx)?;
                     let c = json.next();
                     if c != Some(b':') {
                         return Err(ParseError::ExpectedColon {
                             found: c.map(|i| i as char),
                         });
                     }
-                    ignore_ws(json);
-                    let value = parse_next(json, alloc, ParseState::Value)?;
+                    skip_ws(json, ctx)?;
+                    let value = parse_next(json, alloc, ParseState::Value, ctx)?;
                     contents.insert(key, value);
-                    ignore_ws(json);
+                    skip_ws(json, ctx)?;
                     match json.next() {
                         Some(b'}') => break,
+                        Some(b',') if ctx.trailing_commas() => {
+                            skip_ws(json, ctx)?;
+                            if json.peek_copy() == Some(b'}') {
+                                json.ignore_next();
+                                break;
+                            }
+                        }
                         Some(b',') => continue,
                         v => {
                             return Err(ParseError::ExpectedEndOfObject {
@@ -238,17 +559,107 @@ fn parse_next<'a, 'bump>(
                         }
                     }
                 }
-                return Ok(JsonValue::Object(alloc.alloc(contents)));
+                return Ok(object_value(alloc, contents));
             }
         }
     }
     return Ok(JsonValue::Null);
 }
 
-fn ignore_ws<'a, I: CopyIter<'a, Item = u8>>(json: &mut I) {
-    while json.peek_copy().map_or(false, is_whitespace) {
-        json.ignore_next();
+/// `Bump::alloc` is always inlined, and unoptimized it takes up kilobytes of
+/// stack, so in debug builds it's kept out of the recursive parsers
+#[cfg_attr(debug_assertions, inline(never))]
+pub(crate) fn array_value<'bump>(
+    alloc: &'bump Bump,
+    contents: Vec<'bump, JsonValue<'bump>>,
+) -> JsonValue<'bump> {
+    JsonValue::Array(alloc.alloc(contents))
+}
+
+#[cfg_attr(debug_assertions, inline(never))]
+pub(crate) fn object_value<'bump>(
+    alloc: &'bump Bump,
+    contents: JsonObject<'bump>,
+) -> JsonValue<'bump> {
+    JsonValue::Object(alloc.alloc(contents))
+}
+
+/// Reads a string, number or literal. Kept out of line in debug builds like
+/// `array_value`, so the frames of `parse_next` stay small
+#[cfg_attr(debug_assertions, inline(never))]
+fn parse_scalar<'a, 'bump>(
+    json: &mut SliceIter<'a, u8>,
+    alloc: &'bump Bump,
+    char: u8,
+    ctx: ParseContext,
+) -> JsonResult<JsonValue<'bump>> {
+    let json5 = ctx.options.dialect == Dialect::Json5;
+    if is_string(char) || json5 && char == b'\'' {
+        if ctx.borrow_strings {
+            if let Some(str) = read_borrowed_str_with(json, ctx)? {
+                // SAFETY: `borrow_strings` is only set by `parse_borrowed`,
+                // whose input outlives `'bump`
+                let str = unsafe { mem::transmute::<&'a str, &'bump str>(str) };
+                return Ok(JsonValue::Str(alloc.alloc(str)));
+            }
+        }
+        return Ok(read_string_with(json, alloc, ctx)?.into_value(alloc));
+    }
+    if json5 && (is_number(char) || matches!(char, b'+' | b'.' | b'I' | b'N')) {
+        return Ok(JsonValue::Number(read_json5_number(json)?));
+    }
+    if is_number(char) {
+        return Ok(JsonValue::Number(ctx.number(read_number(json)?)?));
+    }
+    let next_4: [u8; 4] = json
+        .peek_many()
+        .map_or(Err(ParseError::UnexpectedEndOfFile), |v| Ok(v))?;
+    if &next_4 == b"true" {
+        json.ignore_many(4);
+        return Ok(JsonValue::Boolean(true));
+    }
+    if &next_4 == b"null" {
+        json.ignore_many(4);
+        return Ok(JsonValue::Null);
+    }
+    if json.peek_many_ref(5) == Some(b"false") {
+        json.ignore_many(5);
+        return Ok(JsonValue::Boolean(false));
+    }
+    Err(ParseError::ExpectedNextValue {
+        found: json.next().map(|i| i as char),
+    })
+}
+
+/// Reads an object key, out of line like `parse_scalar`
+#[cfg_attr(debug_assertions, inline(never))]
+fn parse_key<'bump>(
+    json: &mut SliceIter<'_, u8>,
+    alloc: &'bump Bump,
+    ctx: ParseContext,
+) -> JsonResult<&'bump str> {
+    match json.peek_copy() {
+        Some(b'"' | b'\'') | None => read_key_str(json, alloc, ctx),
+        Some(_) if ctx.options.dialect == Dialect::Json5 => {
+            Ok(read_identifier(json, alloc)?.into_bump_str())
+        }
+        Some(_) => read_key_str(json, alloc, ctx),
+    }
+}
+
+#[inline]
+fn ignore_ws(json: &mut SliceIter<'_, u8>) {
+    json.skip_whitespace();
+}
+
+/// Skips whitespace, and comments too where `ctx` allows them
+#[inline]
+fn skip_ws(json: &mut SliceIter<'_, u8>, ctx: ParseContext) -> JsonResult<()> {
+    if ctx.comments() {
+        return json5::skip_whitespace(json, ctx.options.dialect == Dialect::Json5);
     }
+    ignore_ws(json);
+    Ok(())
 }
 
 fn is_whitespace(char: u8) -> bool {
@@ -310,6 +721,348 @@ mod tests {
         eprintln!("{:?}", ret);
     }
 
+    #[test]
+    fn borrowed_strings() {
+        let json = br#"{"plain": "no escapes", "escaped": "tab\there", "list": ["a", "\u00e9"]}"#;
+        let bump = Bump::new();
+        let ret = parse_borrowed(json, &bump).unwrap();
+        let obj = match &ret {
+            JsonValue::Object(obj) => obj,
+            _ => panic!("Expected object"),
+        };
+        match obj.get("plain") {
+            Some(JsonValue::Str(str)) => assert!(json.as_ptr_range().contains(&str.as_ptr())),
+            _ => panic!("Expected plain string to be borrowed"),
+        }
+        match obj.get("escaped") {
+            Some(JsonValue::String(str)) => assert_eq!(str.as_str(), "tab\there"),
+            _ => panic!("Expected escaped string to be copied"),
+        }
+        assert_eq!(ret, parse(json, &bump).unwrap());
+
+        let json = br#"{"plain": 1, "k\u0065y": {"inner": 2}}"#;
+        for backend in [Backend::Streaming, Backend::Structural] {
+            let options = ParseOptions {
+                backend,
+                ..Default::default()
+            };
+            let ret = parse_borrowed_with_options(json, &bump, &options).unwrap();
+            let JsonValue::Object(obj) = &ret else {
+                panic!("Expected object");
+            };
+            let (&escaped, inner) = obj.get_key_value("key").unwrap();
+            assert!(!json.as_ptr_range().contains(&escaped.as_ptr()));
+            let JsonValue::Object(inner) = inner else {
+                panic!("Expected object");
+            };
+            for key in obj.keys().chain(inner.keys()).filter(|key| **key != "key") {
+                assert!(json.as_ptr_range().contains(&key.as_ptr()), "{key}");
+            }
+        }
+    }
+
+    #[test]
+    fn whitespace() {
+        let bump = Bump::new();
+        let expected = parse(br#"{"a":[1,{"b":null},"c"],"d":true}"#, &bump).unwrap();
+        for len in [0, 1, 2, 15, 16, 17, 33, 64, 65, 130] {
+            let ws: StdString = " \n\t\r".chars().cycle().take(len).collect();
+            let json = format!(
+                "{ws}{{{ws}\"a\"{ws}:{ws}[{ws}1{ws},{ws}{{ \"b\": null{ws}}}, \"c\"]{ws},\"d\":{ws}true}}{ws}"
+            );
+            assert_eq!(parse(json.as_bytes(), &bump).unwrap(), expected, "{len}");
+        }
+    }
+
+    #[test]
+    fn long_strings() {
+        let bump = Bump::new();
+        for len in [0, 1, 15, 16, 17, 31, 32, 33, 63, 64, 65, 200] {
+            let plain = "ab".repeat(len / 2) + &"c".repeat(len % 2);
+            let json = format!("[\"{plain}\", \"{plain}\\n{plain}\"]");
+            let expected = [plain.clone(), format!("{plain}\n{plain}")];
+            for ret in [
+                parse(json.as_bytes(), &bump).unwrap(),
+                parse_borrowed(json.as_bytes(), &bump).unwrap(),
+            ] {
+                match ret {
+                    JsonValue::Array(arr) => {
+                        let strs: std::vec::Vec<_> =
+                            arr.iter().map(|v| v.as_str().unwrap()).collect();
+                        assert_eq!(strs, expected);
+                    }
+                    _ => panic!("Expected array"),
+                }
+            }
+            let raw_control = format!("\"{plain}\t\"");
+            for ret in [
+                parse(raw_control.as_bytes(), &bump),
+                parse_borrowed(raw_control.as_bytes(), &bump),
+            ] {
+                assert!(matches!(
+                    ret,
+                    Err(JsonError::ParseError(
+                        ParseError::ControlCharacter { found: '\t' },
+                        _
+                    ))
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn utf8_whole_input() {
+        let bump = Bump::new();
+        for backend in [Backend::Streaming, Backend::Structural] {
+            let options = ParseOptions {
+                backend,
+                utf8: Utf8Validation::WholeInput,
+                ..Default::default()
+            };
+            let json = r#"{"café": ["naïve", "\u00e9 \ud83d\ude00"]}"#;
+            let expected = parse(json.as_bytes(), &bump).unwrap();
+            let ret = parse_with_options(json.as_bytes(), &bump, &options).unwrap();
+            assert_eq!(ret, expected);
+            let ret = parse_borrowed_with_options(json.as_bytes(), &bump, &options).unwrap();
+            assert_eq!(ret, expected);
+
+            for (json, offset) in [
+                (&b"[\"ab\xffc\"]"[..], 4),
+                (b"[\"\xe2\x82\"]", 2),
+                (b"[1, \xc0]", 4),
+                (b"\"abc\xf0\x9f", 4),
+            ] {
+                match parse_with_options(json, &bump, &options) {
+                    Err(JsonError::ParseError(ParseError::InvalidUtf8At { offset: at }, _)) => {
+                        assert_eq!(at, offset)
+                    }
+                    other => panic!("{json:?}: {other:?}"),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn string_decoding() {
+        fn parse_both<'a>(
+            json: &'a [u8],
+            bump: &'a Bump,
+            options: &ParseOptions,
+        ) -> Result<JsonValue<'a>, JsonError> {
+            let copied = parse_with_options(json, bump, options);
+            let borrowed = parse_borrowed_with_options(json, bump, options);
+            match (&copied, borrowed) {
+                (Ok(a), Ok(b)) => assert_eq!(*a, b),
+                (Err(JsonError::ParseError(a, _)), Err(JsonError::ParseError(b, _))) => {
+                    assert_eq!(a.to_string(), b.to_string())
+                }
+                (a, b) => panic!("{a:?} != {b:?}"),
+            }
+            copied
+        }
+
+        let bump = Bump::new();
+        for backend in [Backend::Streaming, Backend::Structural] {
+            let with = |strings, json| {
+                let options = ParseOptions {
+                    backend,
+                    strings,
+                    ..Default::default()
+                };
+                parse_both(json, &bump, &options)
+            };
+            let str = |strings, json: &'static str| match with(strings, json.as_bytes()) {
+                Ok(value) => value.as_str().unwrap().to_owned(),
+                Err(e) => panic!("{json}: {e}"),
+            };
+            for strings in [
+                StringDecoding::Reject,
+                StringDecoding::Replace,
+                StringDecoding::Wtf8,
+            ] {
+                assert_eq!(str(strings, r#""a😀b""#), "a😀b");
+                assert_eq!(str(strings, r#""A😀""#), "A😀");
+                assert!(matches!(
+                    with(strings, br#""\u+041""#),
+                    Err(JsonError::ParseError(ParseError::InvalidStringEscape, _))
+                ));
+            }
+
+            let lone = br#"["\uD800", "a\udc00\ud800b"]"#;
+            assert!(matches!(
+                with(StringDecoding::Reject, lone),
+                Err(JsonError::ParseError(
+                    ParseError::LoneSurrogate { code: 0xD800 },
+                    _
+                ))
+            ));
+            assert_eq!(str(StringDecoding::Replace, r#""\uD800""#), "\u{FFFD}");
+            assert_eq!(
+                str(StringDecoding::Replace, r#""a\udc00\ud800b""#),
+                "a\u{FFFD}\u{FFFD}b"
+            );
+            match with(StringDecoding::Wtf8, lone).unwrap() {
+                JsonValue::Array(arr) => match &arr[..] {
+                    [JsonValue::Wtf8(a), JsonValue::Wtf8(b)] => {
+                        assert_eq!(&a[..], b"\xED\xA0\x80");
+                        assert_eq!(&b[..], b"a\xED\xB0\x80\xED\xA0\x80b");
+                    }
+                    other => panic!("{other:?}"),
+                },
+                other => panic!("{other:?}"),
+            }
+
+            let raw = b"[\"a\xffb\"]";
+            for strings in [StringDecoding::Reject, StringDecoding::Wtf8] {
+                assert!(matches!(
+                    with(strings, raw),
+                    Err(JsonError::ParseError(ParseError::InvalidUtf8 { .. }, _))
+                ));
+            }
+            match with(StringDecoding::Replace, raw).unwrap() {
+                JsonValue::Array(arr) => assert_eq!(arr[0].as_str(), Some("a\u{FFFD}b")),
+                other => panic!("{other:?}"),
+            }
+
+            let key = br#"{"\uD800": 1}"#;
+            assert!(matches!(
+                with(StringDecoding::Wtf8, key),
+                Err(JsonError::ParseError(
+                    ParseError::LoneSurrogate { code: 0xD800 },
+                    _
+                ))
+            ));
+            match with(StringDecoding::Replace, key).unwrap() {
+                JsonValue::Object(obj) => assert!(obj.contains_key("\u{FFFD}")),
+                other => panic!("{other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn json5() {
+        let bump = Bump::new();
+        let options = ParseOptions {
+            dialect: Dialect::Json5,
+            ..Default::default()
+        };
+        let json = include_bytes!("../../tests/json5-cases/misc/readme-example.json5");
+        let JsonValue::Object(obj) = parse_with_options(json, &bump, &options).unwrap() else {
+            panic!("Expected object");
+        };
+        assert_eq!(obj["this"].as_str(), Some("is a multi-line string"));
+        assert_eq!(obj["while"], JsonValue::Boolean(true));
+        assert_eq!(obj["hex"], JsonValue::Number(3735928559.0));
+        assert_eq!(obj["half"], JsonValue::Number(0.5));
+        assert_eq!(obj["delta"], JsonValue::Number(10.0));
+        assert_eq!(obj["to"], JsonValue::Number(f64::INFINITY));
+        match &obj["oh"] {
+            JsonValue::Array(arr) => assert_eq!(arr[0].as_str(), Some("we shouldn't forget")),
+            other => panic!("{other:?}"),
+        }
+
+        let json = "['\\x41\\v\\0\\'\\q\"\t', \"\\\u{2028}\u{2028}\\\r\n\"]";
+        let expected = ["A\u{B}\0'q\"\t", "\u{2028}"];
+        for ret in [
+            parse_with_options(json.as_bytes(), &bump, &options),
+            parse_borrowed_with_options(json.as_bytes(), &bump, &options),
+        ] {
+            match ret.unwrap() {
+                JsonValue::Array(arr) => {
+                    let strs: std::vec::Vec<_> = arr.iter().map(|v| v.as_str().unwrap()).collect();
+                    assert_eq!(strs, expected);
+                }
+                other => panic!("{other:?}"),
+            }
+        }
+
+        for json in [
+            &b"{a: 1} x"[..],
+            b"'a\nb'",
+            b"'\\1'",
+            b"[1,,]",
+            b"",
+            b"/* */",
+        ] {
+            assert!(
+                parse_with_options(json, &bump, &options).is_err(),
+                "{json:?}"
+            );
+        }
+        // Plain JSON is unaffected
+        assert!(parse(b"{a: 1}", &bump).is_err());
+        assert!(parse(b"'a'", &bump).is_err());
+    }
+
+    #[test]
+    fn jsonc() {
+        let bump = Bump::new();
+        let commented =
+            b"// settings\n{\n  /* a */ \"a\": [1, /* 2, */ 3], // b\n  \"b // c\": \"/* d */\"\n}";
+        let trailing = b"{\"a\": [1, 3,], \"b // c\": \"/* d */\",}";
+        let both = b"{\"a\": [1, 3, // b\n], /* c */ \"b // c\": \"/* d */\", /* e */ }";
+        let expected = parse(br#"{"a": [1, 3], "b // c": "/* d */"}"#, &bump).unwrap();
+        for backend in [Backend::Streaming, Backend::Structural] {
+            for (comments, trailing_commas) in
+                [(false, false), (true, false), (false, true), (true, true)]
+            {
+                let options = ParseOptions {
+                    backend,
+                    comments,
+                    trailing_commas,
+                    ..Default::default()
+                };
+                for (json, ok) in [
+                    (&commented[..], comments),
+                    (trailing, trailing_commas),
+                    (both, comments && trailing_commas),
+                ] {
+                    let ret = parse_with_options(json, &bump, &options);
+                    match ret {
+                        Ok(ret) if ok => assert_eq!(ret, expected),
+                        Err(_) if !ok => {}
+                        ret => panic!("{comments} {trailing_commas} {json:?}: {ret:?}"),
+                    }
+                }
+                // Otherwise still strict JSON
+                for json in [&b"{a: 1}"[..], b"['a']", b"[1,,]", b"[,]", b"{,}"] {
+                    assert!(
+                        parse_with_options(json, &bump, &options).is_err(),
+                        "{json:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn out_of_range() {
+        let bump = Bump::new();
+        let json = b"[1e400, -1e400]";
+        let infinities = JsonValue::Array(bump.alloc(bumpalo::vec![
+            in &bump;
+            JsonValue::Number(f64::INFINITY),
+            JsonValue::Number(f64::NEG_INFINITY)
+        ]));
+        assert_eq!(parse(json, &bump).unwrap(), infinities);
+        for backend in [Backend::Streaming, Backend::Structural] {
+            let options = ParseOptions {
+                backend,
+                reject_out_of_range: true,
+                ..Default::default()
+            };
+            assert!(matches!(
+                parse_with_options(json, &bump, &options),
+                Err(JsonError::ParseError(
+                    ParseError::InvalidNumber(NumberError::OutOfRange),
+                    _
+                ))
+            ));
+            let ret = parse_with_options(b"1e308", &bump, &options).unwrap();
+            assert_eq!(ret, JsonValue::Number(1e308));
+        }
+    }
+
     #[test]
     fn json_atoms() {
         let string = "[null, true,false,null,  true, false]";
@@ -384,7 +1137,7 @@ mod tests {
                     obj.get("nickname")
                 );
                 obj.insert(
-                    String::from_str_in("nickname", &bump),
+                    bump.alloc_str("nickname"),
                     JsonValue::String(
                         bump.alloc(String::from_utf8_lossy_in(&[0x61; 50000], &bump)),
                     ),
@@ -0,0 +1,239 @@
+//! The parts of JSON5 that plain JSON has nothing like: comments, the wider
+//! set of whitespace, and unquoted object keys. Comments are also allowed on
+//! their own with `ParseOptions::comments`.
+
+use bumpalo::collections::String;
+use bumpalo::Bump;
+
+use crate::slice_iter::{CopyIter, SliceIter};
+use crate::{JsonResult, ParseError};
+
+/// Moves past whitespace and `//` and `/* */` comments, stopping at the start
+/// of a comment that is never closed.
+///
+/// With `json5`, also past the rest of ECMAScript's whitespace: vertical tab,
+/// form feed, the byte-order mark and the Unicode space separators.
+pub fn skip_whitespace(json: &mut SliceIter<'_, u8>, json5: bool) -> JsonResult<()> {
+    loop {
+        json.skip_whitespace();
+        let len = match &json.as_slice()[json.index()..] {
+            _ if !json5 => 0,
+            [0x0B | 0x0C, ..] => 1,
+            // U+00A0
+            [0xC2, 0xA0, ..] => 2,
+            // U+1680, U+2000 to U+200A, U+2028, U+2029, U+202F, U+205F, U+3000,
+            // U+FEFF
+            [0xE1, 0x9A, 0x80, ..]
+            | [0xE2, 0x80, 0x80..=0x8A | 0xA8 | 0xA9 | 0xAF, ..]
+            | [0xE2, 0x81, 0x9F, ..]
+            | [0xE3, 0x80, 0x80, ..]
+            | [0xEF, 0xBB, 0xBF, ..] => 3,
+            _ => 0,
+        };
+        if len > 0 {
+            json.ignore_many(len);
+            continue;
+        }
+        if json.peek_copy() != Some(b'/') {
+            return Ok(());
+        }
+        match json.peek_at_copy(1) {
+            Some(b'/') => {
+                json.take_while_ne_simd([b'\n', b'\r'], 0);
+            }
+            Some(b'*') => {
+                let start = json.index();
+                json.ignore_many(2);
+                loop {
+                    json.take_while_ne_simd([b'*'], 0);
+                    match json.peek_many_ref(2) {
+                        Some(b"*/") => break,
+                        Some(_) => json.ignore_next(),
+                        None => {
+                            json.set_index(start);
+                            return Err(ParseError::UnterminatedComment);
+                        }
+                    }
+                }
+                json.ignore_many(2);
+            }
+            _ => return Ok(()),
+        }
+    }
+}
+
+/// Reads an unquoted object key, an ECMAScript identifier name like `a`,
+/// `$_1`, `café` or `\u0061b`
+pub fn read_identifier<'b>(
+    json: &mut SliceIter<'_, u8>,
+    alloc: &'b Bump,
+) -> JsonResult<String<'b>> {
+    let mut key = String::new_in(alloc);
+    loop {
+        let start = json.index();
+        let char = match next_char(json) {
+            Some('\\') => {
+                let escaped = match json.take_many::<5>() {
+                    Some([b'u', digits @ ..]) => std::str::from_utf8(&digits)
+                        .ok()
+                        .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+                        .filter(|_| digits.iter().all(u8::is_ascii_hexdigit))
+                        .and_then(char::from_u32),
+                    _ => None,
+                };
+                match escaped {
+                    Some(char) if is_identifier_char(char, key.is_empty()) => char,
+                    _ => {
+                        json.set_index(start);
+                        return Err(ParseError::InvalidIdentifier { found: Some('\\') });
+                    }
+                }
+            }
+            Some(char) if is_identifier_char(char, key.is_empty()) => char,
+            found => {
+                json.set_index(start);
+                if key.is_empty() {
+                    return Err(ParseError::InvalidIdentifier { found });
+                }
+                return Ok(key);
+            }
+        };
+        key.push(char);
+    }
+}
+
+fn is_identifier_char(char: char, first: bool) -> bool {
+    match char {
+        '$' | '_' => true,
+        '\u{200C}' | '\u{200D}' => !first,
+        char if char.is_ascii() => char.is_ascii_alphabetic() || !first && char.is_ascii_digit(),
+        char => char.is_alphabetic() || !first && char.is_alphanumeric(),
+    }
+}
+
+/// Decodes the next character of the input, `None` at the end or if it isn't
+/// valid UTF-8
+fn next_char(json: &mut SliceIter<'_, u8>) -> Option<char> {
+    let rest = &json.as_slice()[json.index()..];
+    let window = &rest[..rest.len().min(4)];
+    let valid = match std::str::from_utf8(window) {
+        Ok(valid) => valid,
+        Err(e) => std::str::from_utf8(&window[..e.valid_up_to()]).unwrap(),
+    };
+    let char = valid.chars().next()?;
+    json.ignore_many(char.len_utf8());
+    Some(char)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::*;
+    use crate::{parse, parse_with_options, Dialect, ParseOptions};
+
+    #[test]
+    fn comments() {
+        let cases: [(&[u8], usize); 7] = [
+            (b"  // line\n  1", 12),
+            (b"/* a */ /** b **/1", 17),
+            (b"/* a\n * b\n */\t// c\r\n1", 20),
+            (b"//", 2),
+            (b"/1", 0),
+            (b"\x0B\xC2\xA0\xE2\x80\xA8\xEF\xBB\xBF1", 9),
+            (b"\xE2\x80\xA7", 0),
+        ];
+        for (json, end) in cases {
+            let mut iter = SliceIter::new(json);
+            skip_whitespace(&mut iter, true).unwrap();
+            assert_eq!(iter.index(), end, "{json:?}");
+        }
+        let mut iter = SliceIter::new(b"/* a */\x0B");
+        skip_whitespace(&mut iter, false).unwrap();
+        assert_eq!(iter.index(), 7);
+        for json in [&b"/* a"[..], b"/* a *", b"/*/"] {
+            let mut iter = SliceIter::new(json);
+            assert!(matches!(
+                skip_whitespace(&mut iter, true),
+                Err(ParseError::UnterminatedComment)
+            ));
+        }
+    }
+
+    #[test]
+    fn identifiers() {
+        let bump = Bump::new();
+        for (json, key) in [
+            ("a:", "a"),
+            ("$_1 :", "$_1"),
+            ("café}", "café"),
+            ("\\u0061b:", "ab"),
+            ("ab\\u0063", "abc"),
+            ("π", "π"),
+        ] {
+            let mut iter = SliceIter::new(json.as_bytes());
+            assert_eq!(read_identifier(&mut iter, &bump).unwrap(), key);
+            assert_eq!(
+                iter.index(),
+                json.find([':', ' ', '}']).unwrap_or(json.len())
+            );
+        }
+        for json in ["1a", "-a", "\\u0031", "\\u00zz", ":"] {
+            let mut iter = SliceIter::new(json.as_bytes());
+            assert!(
+                matches!(
+                    read_identifier(&mut iter, &bump),
+                    Err(ParseError::InvalidIdentifier { .. })
+                ),
+                "{json}"
+            );
+        }
+    }
+
+    /// Runs every case under `dir`, by the json5-tests conventions: a
+    /// `.json` file parses the same as JSON, a `.json5` file parses, and a
+    /// `.js` or `.txt` file doesn't. `todo` holds cases upstream hasn't
+    /// decided on yet, so it is skipped
+    fn run_cases(dir: &Path) -> usize {
+        let options = ParseOptions {
+            dialect: Dialect::Json5,
+            ..Default::default()
+        };
+        let mut cases = 0;
+        for category in fs::read_dir(dir).unwrap() {
+            let category = category.unwrap().path();
+            if !category.is_dir() || category.ends_with("todo") {
+                continue;
+            }
+            for case in fs::read_dir(category).unwrap() {
+                let path = case.unwrap().path();
+                let json = fs::read(&path).unwrap();
+                let bump = Bump::new();
+                let ret = parse_with_options(&json, &bump, &options);
+                match path.extension().and_then(|ext| ext.to_str()) {
+                    Some("json") => {
+                        let expected = parse(&json, &bump).unwrap();
+                        assert_eq!(ret.unwrap(), expected, "{path:?}");
+                    }
+                    Some("json5") => assert!(ret.is_ok(), "{path:?}: {ret:?}"),
+                    Some("js" | "txt") => assert!(ret.is_err(), "{path:?}: {ret:?}"),
+                    _ => continue,
+                }
+                cases += 1;
+            }
+        }
+        cases
+    }
+
+    #[test]
+    fn suite() {
+        let tests = Path::new(env!("CARGO_MANIFEST_DIR")).join("../tests");
+        // Only there once `tests/vendor-json5-tests.sh` has been run
+        let upstream = tests.join("json5");
+        if upstream.is_dir() {
+            assert!(run_cases(&upstream) > 0);
+        }
+        assert!(run_cases(&tests.join("json5-cases")) > 80);
+    }
+}
@@ -1,2 +1,4 @@
+pub mod json5;
 pub mod number;
+pub mod skip;
 pub mod string;
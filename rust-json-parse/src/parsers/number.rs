@@ -1,24 +1,325 @@
-use std::str::FromStr;
-
-use crate::{slice_iter::CopyIter, JsonResult, ParseError};
-
-pub fn read_number<'a, I: CopyIter<'a, Item = u8>>(json: &mut I) -> JsonResult<f64> {
-    let mut num_buf: [u8; 320] = [0; 320];
-    let mut num_len: usize = 0;
-    while let Some(byte) = json.peek_copy() {
-        match byte {
-            b'0'..=b'9' | b'.' | b'e' | b'E' | b'-' | b'+' => {
-                if num_len >= num_buf.len() {
-                    return Err(ParseError::InvalidNumberLiteral);
+use crate::slice_iter::SliceIter;
+use crate::{JsonResult, NumberError};
+
+/// Powers of ten that are exactly representable as an `f64`
+const POW10: [f64; 23] = [
+    1e0, 1e1, 1e2, 1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10, 1e11, 1e12, 1e13, 1e14, 1e15, 1e16,
+    1e17, 1e18, 1e19, 1e20, 1e21, 1e22,
+];
+
+/// Up to this many digits always fit in a `u64`
+const MAX_FAST_DIGITS: usize = 19;
+
+/// Reads a number straight out of the input, checking it against the JSON
+/// grammar.
+///
+/// Integers and short decimals are computed exactly from their digits
+/// (Clinger's fast path). Everything else, including mantissas of any length,
+/// goes to `f64::from_str`, whose Eisel-Lemire implementation falls back to
+/// big decimal arithmetic when that can't round correctly. A number too
+/// large for an `f64`, like `1e400`, is infinity, unless
+/// `ParseOptions::reject_out_of_range` is set.
+pub fn read_number(json: &mut SliceIter<'_, u8>) -> JsonResult<f64> {
+    let bytes = json.as_slice();
+    let start = json.index();
+    let mut i = start;
+    let digit = |i: usize| bytes.get(i).filter(|b| b.is_ascii_digit()).copied();
+
+    let negative = bytes.get(i) == Some(&b'-');
+    if negative {
+        i += 1;
+    }
+
+    let mut mantissa: u64 = 0;
+    let mut digits = 0;
+    match digit(i) {
+        Some(b'0') => {
+            i += 1;
+            if digit(i).is_some() {
+                json.set_index(i);
+                return Err(NumberError::LeadingZero.into());
+            }
+        }
+        Some(_) => {
+            while let Some(d) = digit(i) {
+                if digits < MAX_FAST_DIGITS {
+                    mantissa = mantissa * 10 + (d - b'0') as u64;
                 }
-                num_buf[num_len] = json.next().unwrap();
-                num_len += 1;
+                digits += 1;
+                i += 1;
             }
-            _ => break,
+        }
+        None => {
+            json.set_index(i);
+            return Err(NumberError::MissingDigits.into());
+        }
+    }
+
+    let mut fraction_digits = 0;
+    if bytes.get(i) == Some(&b'.') {
+        i += 1;
+        while let Some(d) = digit(i) {
+            // Leading zeros of the fraction don't count towards the precision
+            if digits > 0 || d != b'0' {
+                if digits < MAX_FAST_DIGITS {
+                    mantissa = mantissa * 10 + (d - b'0') as u64;
+                }
+                digits += 1;
+            }
+            fraction_digits += 1;
+            i += 1;
+        }
+        if fraction_digits == 0 {
+            json.set_index(i);
+            return Err(NumberError::MissingFractionDigits.into());
         }
     }
-    let number = f64::from_str(std::str::from_utf8(&num_buf[0..num_len]).unwrap())
-        .map_err(|_| ParseError::InvalidNumberLiteral)?;
 
-    Ok(number)
+    let mut exponent: i64 = 0;
+    if let Some(b'e' | b'E') = bytes.get(i) {
+        i += 1;
+        let negative_exponent = bytes.get(i) == Some(&b'-');
+        if let Some(b'-' | b'+') = bytes.get(i) {
+            i += 1;
+        }
+        if digit(i).is_none() {
+            json.set_index(i);
+            return Err(NumberError::MissingExponentDigits.into());
+        }
+        while let Some(d) = digit(i) {
+            // Far beyond where every `f64` has become zero or infinity
+            if exponent < 100_000 {
+                exponent = exponent * 10 + (d - b'0') as i64;
+            }
+            i += 1;
+        }
+        if negative_exponent {
+            exponent = -exponent;
+        }
+    }
+    json.set_index(i);
+
+    if digits <= MAX_FAST_DIGITS && fraction_digits == 0 && exponent == 0 {
+        // Integers convert exactly, or rounded to nearest like `from_str`
+        let value = mantissa as f64;
+        return Ok(if negative { -value } else { value });
+    }
+    if digits <= MAX_FAST_DIGITS && mantissa < 1 << 53 {
+        let exponent = exponent - fraction_digits;
+        let value = mantissa as f64;
+        let value = match exponent {
+            0 => Some(value),
+            1..=22 => Some(value * POW10[exponent as usize]),
+            -22..=-1 => Some(value / POW10[-exponent as usize]),
+            _ => None,
+        };
+        if let Some(value) = value {
+            return Ok(if negative { -value } else { value });
+        }
+    }
+
+    // SAFETY: everything between `start` and `i` was checked to be ASCII
+    let literal = unsafe { std::str::from_utf8_unchecked(&bytes[start..i]) };
+    literal
+        .parse::<f64>()
+        .map_err(|_| NumberError::MissingDigits.into())
+}
+
+/// Reads a JSON5 number, which can also have a leading `+`, a leading or
+/// trailing decimal point, be hexadecimal, or be `Infinity` or `NaN`.
+///
+/// Numbers too large for an `f64` become infinity, as they would in
+/// JavaScript, whatever `ParseOptions::reject_out_of_range` says.
+pub fn read_json5_number(json: &mut SliceIter<'_, u8>) -> JsonResult<f64> {
+    let bytes = json.as_slice();
+    let start = json.index();
+    let mut i = start;
+    let negative = bytes.get(i) == Some(&b'-');
+    if let Some(b'-' | b'+') = bytes.get(i) {
+        i += 1;
+    }
+    let rest = &bytes[i..];
+    if rest.starts_with(b"Infinity") {
+        json.set_index(i + 8);
+        return Ok(if negative {
+            f64::NEG_INFINITY
+        } else {
+            f64::INFINITY
+        });
+    }
+    if rest.starts_with(b"NaN") {
+        json.set_index(i + 3);
+        return Ok(f64::NAN);
+    }
+    let digits = |mut i: usize| {
+        while bytes.get(i).map_or(false, u8::is_ascii_digit) {
+            i += 1;
+        }
+        i
+    };
+
+    if let [b'0', b'x' | b'X', ..] = rest {
+        i += 2;
+        let mut value: f64 = 0.0;
+        let digits_start = i;
+        while let Some(digit) = bytes.get(i).and_then(|b| (*b as char).to_digit(16)) {
+            value = value * 16.0 + digit as f64;
+            i += 1;
+        }
+        json.set_index(i);
+        if i == digits_start {
+            return Err(NumberError::MissingDigits.into());
+        }
+        return Ok(if negative { -value } else { value });
+    }
+
+    let int_start = i;
+    i = digits(i);
+    if i - int_start > 1 && bytes[int_start] == b'0' {
+        json.set_index(int_start + 1);
+        return Err(NumberError::LeadingZero.into());
+    }
+    let mut has_digits = i > int_start;
+    if bytes.get(i) == Some(&b'.') {
+        let fraction_start = i + 1;
+        i = digits(fraction_start);
+        has_digits |= i > fraction_start;
+    }
+    if !has_digits {
+        json.set_index(i);
+        return Err(NumberError::MissingDigits.into());
+    }
+    if let Some(b'e' | b'E') = bytes.get(i) {
+        i += 1;
+        if let Some(b'-' | b'+') = bytes.get(i) {
+            i += 1;
+        }
+        let exponent_start = i;
+        i = digits(i);
+        if i == exponent_start {
+            json.set_index(i);
+            return Err(NumberError::MissingExponentDigits.into());
+        }
+    }
+    json.set_index(i);
+
+    // SAFETY: everything between `start` and `i` was checked to be ASCII
+    let literal = unsafe { std::str::from_utf8_unchecked(&bytes[start..i]) };
+    // `from_str` takes a leading `+` and either side of the point being empty
+    Ok(literal.parse::<f64>().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ParseError;
+
+    fn read(json: &str) -> JsonResult<f64> {
+        let mut iter = SliceIter::new(json.as_bytes());
+        let num = read_number(&mut iter)?;
+        assert_eq!(iter.index(), json.len(), "{json}");
+        Ok(num)
+    }
+
+    #[test]
+    fn same_as_std() {
+        let long = format!("0.{}1e10", "0".repeat(400));
+        let longer = format!("{}.{}", "9".repeat(300), "3".repeat(500));
+        let numbers = [
+            "0",
+            "-0",
+            "1",
+            "-12",
+            "9007199254740993",
+            "18446744073709551615",
+            "123456789012345678901234567890",
+            "0.1",
+            "0.000001",
+            "3.141592653589793",
+            "2.2250738585072014e-308",
+            "4.9e-324",
+            "1e-400",
+            "1.7976931348623157e308",
+            "1e308",
+            "1E+22",
+            "1e23",
+            "123.456e-7",
+            "0.30000000000000004",
+            "7.3005072578017796e-06",
+            &long,
+            &longer,
+        ];
+        for number in numbers {
+            let expected: f64 = number.parse().unwrap();
+            let num = read(number).unwrap();
+            assert_eq!(num.to_bits(), expected.to_bits(), "{number}");
+        }
+    }
+
+    #[test]
+    fn json5() {
+        for (json, expected) in [
+            ("+1", 1.0),
+            (".5", 0.5),
+            ("5.", 5.0),
+            ("-.5e1", -5.0),
+            ("5.e2", 500.0),
+            ("0x1F", 31.0),
+            ("-0XaB", -171.0),
+            ("0xC8e4", 51428.0),
+            ("Infinity", f64::INFINITY),
+            ("-Infinity", f64::NEG_INFINITY),
+            ("+Infinity", f64::INFINITY),
+            ("1e400", f64::INFINITY),
+            ("12", 12.0),
+        ] {
+            let mut iter = SliceIter::new(json.as_bytes());
+            assert_eq!(read_json5_number(&mut iter).unwrap(), expected, "{json}");
+            assert_eq!(iter.index(), json.len(), "{json}");
+        }
+        for json in ["NaN", "-NaN"] {
+            let mut iter = SliceIter::new(json.as_bytes());
+            assert!(read_json5_number(&mut iter).unwrap().is_nan());
+        }
+        for (json, expected) in [
+            ("0x", NumberError::MissingDigits),
+            (".", NumberError::MissingDigits),
+            ("+", NumberError::MissingDigits),
+            ("-.e1", NumberError::MissingDigits),
+            ("010", NumberError::LeadingZero),
+            ("1.e", NumberError::MissingExponentDigits),
+        ] {
+            let mut iter = SliceIter::new(json.as_bytes());
+            match read_json5_number(&mut iter) {
+                Err(ParseError::InvalidNumber(e)) => assert_eq!(e, expected, "{json}"),
+                other => panic!("{json}: {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let errors = [
+            ("01", NumberError::LeadingZero),
+            ("-00", NumberError::LeadingZero),
+            ("-", NumberError::MissingDigits),
+            ("-.5", NumberError::MissingDigits),
+            ("1.", NumberError::MissingFractionDigits),
+            ("1.e5", NumberError::MissingFractionDigits),
+            ("1e", NumberError::MissingExponentDigits),
+            ("1e+", NumberError::MissingExponentDigits),
+        ];
+        for (json, expected) in errors {
+            let mut iter = SliceIter::new(json.as_bytes());
+            match read_number(&mut iter) {
+                Err(ParseError::InvalidNumber(e)) => assert_eq!(e, expected, "{json}"),
+                other => panic!("{json}: {other:?}"),
+            }
+        }
+        assert_eq!(read("1e400").unwrap(), f64::INFINITY);
+        // Anything after the number is left for the caller
+        let mut iter = SliceIter::new(b"12.5,");
+        assert_eq!(read_number(&mut iter).unwrap(), 12.5);
+        assert_eq!(iter.index(), 4);
+    }
 }
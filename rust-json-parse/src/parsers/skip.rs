@@ -0,0 +1,115 @@
+use std::ops::Range;
+
+use crate::slice_iter::{CopyIter, SliceIter};
+use crate::{ignore_ws, is_whitespace, JsonResult, ParseError};
+
+/// Finds the byte range of the next value in `json` at or after `offset`,
+/// without parsing it, for copying a sub-document out verbatim or indexing
+/// where values are.
+///
+/// Whitespace before the value is skipped. Like `skip_value`, this only
+/// follows strings and brackets, so the value inside the range isn't
+/// validated.
+pub fn value_range(json: &[u8], offset: usize) -> Result<Range<usize>, ParseError> {
+    let mut iter = SliceIter::new(json);
+    iter.set_index(offset);
+    ignore_ws(&mut iter);
+    let start = iter.index();
+    if let Some(found @ (b',' | b':' | b']' | b'}')) = iter.peek_copy() {
+        return Err(ParseError::ExpectedNextValue {
+            found: Some(found as char),
+        });
+    }
+    skip_value(&mut iter)?;
+    Ok(start..iter.index())
+}
+
+/// Moves past the next value without parsing it.
+///
+/// Only the ends of strings and the nesting depth of brackets are tracked, so
+/// the value isn't validated: mismatched brackets like `[}` or bad literals
+/// are only caught if the value is parsed later.
+pub fn skip_value(json: &mut SliceIter<'_, u8>) -> JsonResult<()> {
+    match json.peek_copy() {
+        Some(b'"') => {
+            json.ignore_next();
+            skip_string_rest(json)
+        }
+        Some(b'[' | b'{') => skip_container(json),
+        Some(_) => {
+            CopyIter::take_while(json, |byte| {
+                !is_whitespace(byte) && !matches!(byte, b',' | b':' | b']' | b'}')
+            });
+            Ok(())
+        }
+        None => Err(ParseError::UnexpectedEndOfFile),
+    }
+}
+
+/// Skips to just past the closing quote of a string whose opening quote has
+/// already been read
+pub(crate) fn skip_string_rest(json: &mut SliceIter<'_, u8>) -> JsonResult<()> {
+    loop {
+        json.take_while_ne_simd([b'"', b'\\'], 0);
+        match json.next() {
+            Some(b'"') => return Ok(()),
+            // A backslash, the next byte can't end the string
+            Some(_) => json.ignore_next(),
+            None => return Err(ParseError::UnexpectedEndOfFile),
+        }
+    }
+}
+
+fn skip_container(json: &mut SliceIter<'_, u8>) -> JsonResult<()> {
+    let mut depth: usize = 0;
+    loop {
+        json.take_while_ne_simd([b'"', b'[', b']', b'{', b'}'], 0);
+        match json.next() {
+            Some(b'"') => skip_string_rest(json)?,
+            Some(b'[' | b'{') => depth += 1,
+            Some(_) => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(());
+                }
+            }
+            None => return Err(ParseError::UnexpectedEndOfFile),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn value_ranges() {
+        let json =
+            br#"{"id": 1, "payload": {"text": "a \"}\" b", "list": [1, [2], {}]}, "ok": true}"#;
+        let at = |offset| value_range(json, offset).map(|range| &json[range]);
+        assert_eq!(at(0).unwrap(), &json[..]);
+        let payload = json.windows(8).position(|w| w == b"payload\"").unwrap() + 8;
+        assert_eq!(
+            at(payload + 1).unwrap(),
+            br#"{"text": "a \"}\" b", "list": [1, [2], {}]}"#
+        );
+        assert_eq!(at(6).unwrap(), b"1");
+        assert_eq!(at(json.len() - 5).unwrap(), b"true");
+        assert!(matches!(
+            value_range(json, 8),
+            Err(ParseError::ExpectedNextValue { found: Some(',') })
+        ));
+        assert!(matches!(
+            value_range(b"[1, {", 0),
+            Err(ParseError::UnexpectedEndOfFile)
+        ));
+        assert!(matches!(
+            value_range(b"\"a\\\"", 0),
+            Err(ParseError::UnexpectedEndOfFile)
+        ));
+        assert!(matches!(
+            value_range(b"[1]  ", 3),
+            Err(ParseError::UnexpectedEndOfFile)
+        ));
+    }
+}
@@ -1,63 +1,241 @@
-use std::simd::u8x16;
-
 use bumpalo::collections::{String, Vec};
 use bumpalo::Bump;
-use itertools::Itertools;
 
 use crate::slice_iter::{CopyIter, SliceIter};
-use crate::{JsonResult, ParseError};
+use crate::{Dialect, JsonResult, JsonValue, ParseContext, ParseError, StringDecoding};
+
+/// A string decoded under one of the `StringDecoding` policies
+pub(crate) enum Decoded<'b> {
+    Str(String<'b>),
+    /// Only from `StringDecoding::Wtf8`, for strings with a lone surrogate
+    Wtf8(Vec<'b, u8>),
+}
+
+impl<'b> Decoded<'b> {
+    pub(crate) fn into_value(self, alloc: &'b Bump) -> JsonValue<'b> {
+        match self {
+            Decoded::Str(str) => JsonValue::String(alloc.alloc(str)),
+            Decoded::Wtf8(bytes) => JsonValue::Wtf8(alloc.alloc(bytes)),
+        }
+    }
+}
 
 pub fn read_string<'a, 'b>(
     json: &mut SliceIter<'a, u8>,
     alloc: &'b Bump,
 ) -> JsonResult<String<'b>> {
-    let c = json.next();
-    if Some(b'"') != c {
-        return Err(ParseError::StringQuote {
-            found: c.map(|v| v as char),
-        });
+    read_key(json, alloc, ParseContext::default())
+}
+
+/// Reads an object key, which has to be valid Unicode even with
+/// `StringDecoding::Wtf8`
+#[inline]
+pub(crate) fn read_key<'b>(
+    json: &mut SliceIter<'_, u8>,
+    alloc: &'b Bump,
+    ctx: ParseContext,
+) -> JsonResult<String<'b>> {
+    match read_string_with(json, alloc, ctx)? {
+        Decoded::Str(str) => Ok(str),
+        Decoded::Wtf8(bytes) => Err(ParseError::LoneSurrogate {
+            code: first_surrogate(&bytes),
+        }),
+    }
+}
+
+/// Reads an object key like `read_key`, but with `borrow_strings` a key
+/// without escapes is borrowed from the input instead of copied
+#[inline]
+pub(crate) fn read_key_str<'a, 'b>(
+    json: &mut SliceIter<'a, u8>,
+    alloc: &'b Bump,
+    ctx: ParseContext,
+) -> JsonResult<&'b str> {
+    if ctx.borrow_strings {
+        if let Some(key) = read_borrowed_str_with(json, ctx)? {
+            // SAFETY: `borrow_strings` is only set by `parse_borrowed`, whose
+            // input outlives `'b`
+            return Ok(unsafe { std::mem::transmute::<&'a str, &'b str>(key) });
+        }
     }
+    Ok(read_key(json, alloc, ctx)?.into_bump_str())
+}
+
+/// Reads a string like `read_string`, decoding it with `ctx`'s
+/// `StringDecoding`, and as a JSON5 string under `Dialect::Json5`.
+///
+/// Strings aren't checked for UTF-8 again once the whole input has been.
+#[inline]
+pub(crate) fn read_string_with<'b>(
+    json: &mut SliceIter<'_, u8>,
+    alloc: &'b Bump,
+    ctx: ParseContext,
+) -> JsonResult<Decoded<'b>> {
+    let json5 = ctx.options.dialect == Dialect::Json5;
+    let quote = open_quote(json, json5)?;
+    let decoding = ctx.options.strings;
+    // Once a surrogate is in the buffer it can't be validated as a whole, so
+    // with WTF-8 each run of raw bytes is checked as it is copied instead
+    let check_runs = decoding == StringDecoding::Wtf8 && !ctx.utf8_validated;
+    let mut wtf8 = false;
     let mut buf: Vec<u8> = Vec::new_in(alloc);
-    // if 12 or under, probably just short key
-
-    'parsing_block: {
-        let conditions = [u8x16::splat(b'"'), u8x16::splat(b'\\')];
-        loop {
-            let chunk = json.take_while_ne_simd(conditions, |ch| ch != b'"' && ch != b'\\');
-            buf.reserve_exact(chunk.len());
-            let offset = buf.len();
-            unsafe {
-                buf.as_mut_ptr()
-                    .add(offset)
-                    .copy_from_nonoverlapping(chunk.as_ptr(), chunk.len());
-                buf.set_len(offset + chunk.len());
-            };
-
-            for _ in 0..16 {
-                match json.next() {
-                    Some(b'"') => break 'parsing_block,
-                    Some(b'\\') => escape(json, &mut buf)?,
-                    Some(ch) => buf.push(ch),
-                    None => return Err(ParseError::UnexpectedEndOfFile),
+    loop {
+        let run = json.take_while_ne_simd([quote, b'\\'], 0x20);
+        if check_runs && simdutf8::basic::from_utf8(run).is_err() {
+            return Err(invalid_utf8(run));
+        }
+        buf.extend_from_slice(run);
+        match json.next() {
+            Some(ch) if ch == quote => break,
+            Some(b'\\') => wtf8 |= escape(json, &mut buf, decoding, json5)?,
+            // JSON5 strings can have any raw control character but a line break
+            Some(ch) if json5 && ch != b'\n' && ch != b'\r' => buf.push(ch),
+            Some(ch) => return Err(ParseError::ControlCharacter { found: ch as char }),
+            None => return Err(ParseError::UnexpectedEndOfFile),
+        }
+    }
+    buf.shrink_to_fit();
+    if wtf8 {
+        return Ok(Decoded::Wtf8(buf));
+    }
+    if ctx.utf8_validated || check_runs || simdutf8::basic::from_utf8(&buf).is_ok() {
+        return Ok(Decoded::Str(unsafe { String::from_utf8_unchecked(buf) }));
+    }
+    if decoding == StringDecoding::Replace {
+        return Ok(Decoded::Str(String::from_utf8_lossy_in(&buf, alloc)));
+    }
+    Err(invalid_utf8(&buf))
+}
+
+/// Reads a string that contains no escapes straight out of the input, without
+/// copying it.
+///
+/// Returns `None`, leaving `json` where it was, if the string has an escape in
+/// it, so the caller can fall back to `read_string`.
+#[inline]
+pub fn read_borrowed_str<'a>(json: &mut SliceIter<'a, u8>) -> JsonResult<Option<&'a str>> {
+    read_borrowed_str_with(json, ParseContext::default())
+}
+
+/// Reads a borrowed string like `read_borrowed_str`.
+///
+/// Also returns `None` for invalid UTF-8 with `StringDecoding::Replace`, and
+/// for raw control characters in JSON5, as those strings have to be copied.
+#[inline]
+pub(crate) fn read_borrowed_str_with<'a>(
+    json: &mut SliceIter<'a, u8>,
+    ctx: ParseContext,
+) -> JsonResult<Option<&'a str>> {
+    let start = json.index();
+    let json5 = ctx.options.dialect == Dialect::Json5;
+    let quote = open_quote(json, json5)?;
+    let chunk = json.take_while_ne_simd([quote, b'\\'], 0x20);
+    match json.next() {
+        // SAFETY: the whole input was already validated
+        Some(ch) if ch == quote && ctx.utf8_validated => {
+            Ok(Some(unsafe { std::str::from_utf8_unchecked(chunk) }))
+        }
+        Some(ch) if ch == quote => match simdutf8::basic::from_utf8(chunk) {
+            Ok(str) => Ok(Some(str)),
+            Err(_) if ctx.options.strings == StringDecoding::Replace => {
+                json.set_index(start);
+                Ok(None)
+            }
+            Err(_) => Err(invalid_utf8(chunk)),
+        },
+        Some(b'\\') => {
+            json.set_index(start);
+            Ok(None)
+        }
+        Some(_) if json5 => {
+            json.set_index(start);
+            Ok(None)
+        }
+        Some(ch) => Err(ParseError::ControlCharacter { found: ch as char }),
+        None => Err(ParseError::UnexpectedEndOfFile),
+    }
+}
+
+/// Checks a string like `read_string` would read it, without copying it
+/// anywhere.
+///
+/// Escapes are ASCII, so each raw run between them has to be valid UTF-8 on
+/// its own.
+pub(crate) fn validate_string(json: &mut SliceIter<'_, u8>) -> JsonResult<()> {
+    open_quote(json, false)?;
+    loop {
+        let run = json.take_while_ne_simd([b'"', b'\\'], 0x20);
+        if simdutf8::basic::from_utf8(run).is_err() {
+            return Err(invalid_utf8(run));
+        }
+        match json.next() {
+            Some(b'"') => return Ok(()),
+            Some(b'\\') => validate_escape(json)?,
+            Some(ch) => return Err(ParseError::ControlCharacter { found: ch as char }),
+            None => return Err(ParseError::UnexpectedEndOfFile),
+        }
+    }
+}
+
+/// Checks the escape after a `\`, as `escape` reads it under
+/// `StringDecoding::Reject`
+fn validate_escape(json: &mut SliceIter<'_, u8>) -> JsonResult<()> {
+    match json.next() {
+        Some(b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't') => Ok(()),
+        Some(b'u') => {
+            let unit = json
+                .take_many::<4>()
+                .map_or(Err(ParseError::InvalidStringEscape), |v| hex(&v))?;
+            match unit {
+                0xD800..=0xDBFF => {
+                    let low = match json.peek_many_ref(6) {
+                        Some([b'\\', b'u', low @ ..]) => hex(low).ok(),
+                        _ => None,
+                    };
+                    match low {
+                        Some(0xDC00..=0xDFFF) => {
+                            json.ignore_many(6);
+                            Ok(())
+                        }
+                        _ => Err(ParseError::LoneSurrogate { code: unit }),
+                    }
                 }
+                0xDC00..=0xDFFF => Err(ParseError::LoneSurrogate { code: unit }),
+                _ => Ok(()),
             }
         }
+        _ => Err(ParseError::InvalidStringEscape),
     }
-    buf.shrink_to_fit();
-    // Ok(String::from_utf8(buf).map_err(|e| ParseError::InvalidUtf8 {
-    //     string: std::string::String::from_utf8_lossy(e.as_bytes()).to_string(),
-    // })?)
-    if simdutf8::basic::from_utf8(&buf).is_ok() {
-        Ok(unsafe { String::from_utf8_unchecked(buf) })
-    } else {
-        Err(ParseError::InvalidUtf8 {
-            string: std::string::String::from_utf8_lossy(&buf).to_string(),
-        })
+}
+
+/// Reads the opening quote of a string, returning it, as JSON5 strings can
+/// also be single quoted
+#[inline]
+fn open_quote(json: &mut SliceIter<'_, u8>, json5: bool) -> JsonResult<u8> {
+    match json.next() {
+        Some(b'"') => Ok(b'"'),
+        Some(b'\'') if json5 => Ok(b'\''),
+        c => Err(ParseError::StringQuote {
+            found: c.map(|v| v as char),
+        }),
+    }
+}
+
+fn invalid_utf8(bytes: &[u8]) -> ParseError {
+    ParseError::InvalidUtf8 {
+        string: std::string::String::from_utf8_lossy(bytes).to_string(),
     }
 }
 
+/// Reads the escape after a `\`, returning whether it wrote a lone surrogate
+/// as WTF-8
 #[inline]
-fn escape<'a, I: CopyIter<'a, Item = u8>>(json: &mut I, buf: &mut Vec<u8>) -> JsonResult<()> {
+fn escape<'a, I: CopyIter<'a, Item = u8>>(
+    json: &mut I,
+    buf: &mut Vec<u8>,
+    decoding: StringDecoding,
+    json5: bool,
+) -> JsonResult<bool> {
     let escape = json
         .next()
         .map_or(Err(ParseError::InvalidStringEscape), |v| Ok(v))?;
@@ -69,53 +247,100 @@ fn escape<'a, I: CopyIter<'a, Item = u8>>(json: &mut I, buf: &mut Vec<u8>) -> Js
         b'r' => buf.push(0x0D), // Carriage return char
         b't' => buf.push(0x09), // Tab char
         b'u' => {
-            let code = json
+            let unit = json
                 .take_many::<4>()
-                .map_or(Err(ParseError::InvalidStringEscape), |v| Ok(v))?;
-            let mut codepoint = [
-                u16::from_str_radix(
-                    &std::str::from_utf8(&code).map_err(|_| ParseError::InvalidUtf8 {
-                        string: std::string::String::from_utf8_lossy(&code).to_string(),
-                    })?,
-                    16,
-                )
-                .map_err(|_| ParseError::InvalidStringEscape)?,
-                0,
-            ];
-            let mut utf16_len: usize = 1;
-            if json.peek_many_ref(2) == Some(b"\\u") {
-                json.ignore_many(2);
-                let second_code = json
-                    .take_many::<4>()
-                    .map_or(Err(ParseError::InvalidStringEscape), |v| Ok(v))?;
-                codepoint[1] = u16::from_str_radix(
-                    &std::str::from_utf8(&second_code).map_err(|_| ParseError::InvalidUtf8 {
-                        string: std::string::String::from_utf8_lossy(&second_code).to_string(),
-                    })?,
-                    16,
-                )
-                .map_err(|_| ParseError::InvalidStringEscape)?;
-                utf16_len += 1;
+                .map_or(Err(ParseError::InvalidStringEscape), |v| hex(&v))?;
+            let mut code = Some(unit as u32);
+            if (0xD800..0xDC00).contains(&unit) {
+                // A high surrogate only makes a code point with a low one
+                // escaped right after it, anything else is left for the loop
+                let low = match json.peek_many_ref(6) {
+                    Some([b'\\', b'u', low @ ..]) => hex(low).ok(),
+                    _ => None,
+                };
+                code = match low {
+                    Some(low @ 0xDC00..=0xDFFF) => {
+                        json.ignore_many(6);
+                        let (high, low) = (unit as u32 - 0xD800, low as u32 - 0xDC00);
+                        Some(0x10000 + (high << 10) + low)
+                    }
+                    _ => None,
+                };
             }
-            std::char::decode_utf16(codepoint[0..utf16_len].into_iter().copied())
-                .map(|r| match r {
-                    Ok(char) => {
-                        let mut dst: [u8; 4] = [0; 4];
-                        buf.extend_from_slice(char.encode_utf8(&mut dst).as_bytes());
-                        Ok(())
+            match code.and_then(char::from_u32) {
+                Some(char) => {
+                    let mut dst: [u8; 4] = [0; 4];
+                    buf.extend_from_slice(char.encode_utf8(&mut dst).as_bytes());
+                }
+                None => match decoding {
+                    StringDecoding::Reject => {
+                        return Err(ParseError::LoneSurrogate { code: unit });
                     }
-                    Err(byte) => {
-                        if byte.unpaired_surrogate() <= 0x1F {
-                            buf.push(byte.unpaired_surrogate() as u8);
-                            Ok(())
-                        } else {
-                            return Err(ParseError::InvalidStringEscape);
-                        }
+                    StringDecoding::Replace => {
+                        buf.extend_from_slice(
+                            char::REPLACEMENT_CHARACTER
+                                .encode_utf8(&mut [0; 4])
+                                .as_bytes(),
+                        );
+                    }
+                    StringDecoding::Wtf8 => {
+                        // Encoded like any other three byte code point
+                        buf.extend_from_slice(&[
+                            0xE0 | (unit >> 12) as u8,
+                            0x80 | (unit >> 6 & 0x3F) as u8,
+                            0x80 | (unit & 0x3F) as u8,
+                        ]);
+                        return Ok(true);
                     }
-                })
-                .try_collect()?;
+                },
+            }
+        }
+        b'\'' if json5 => buf.push(b'\''),
+        b'v' if json5 => buf.push(0x0B),
+        b'0' if json5 && !json.peek_copy().map_or(false, |b| b.is_ascii_digit()) => buf.push(0),
+        b'x' if json5 => {
+            let code = json
+                .take_many::<2>()
+                .map_or(Err(ParseError::InvalidStringEscape), |v| hex(&v))?;
+            let mut dst: [u8; 4] = [0; 4];
+            buf.extend_from_slice(char::from(code as u8).encode_utf8(&mut dst).as_bytes());
+        }
+        // Line continuations, a line break escaped to leave it out
+        b'\n' if json5 => {}
+        b'\r' if json5 => {
+            if json.peek_copy() == Some(b'\n') {
+                json.ignore_next();
+            }
         }
+        // U+2028 and U+2029
+        0xE2 if json5 && matches!(json.peek_many_ref(2), Some([0x80, 0xA8 | 0xA9])) => {
+            json.ignore_many(2)
+        }
+        // Any other character escapes to itself, other than digits
+        _ if json5 && !escape.is_ascii_digit() => buf.push(escape),
         _ => return Err(ParseError::InvalidStringEscape),
     }
-    Ok(())
+    Ok(false)
+}
+
+/// Parses the hex digits of a `\u` or `\x` escape
+fn hex(digits: &[u8]) -> JsonResult<u16> {
+    let mut unit = 0;
+    for &digit in digits {
+        let digit = (digit as char)
+            .to_digit(16)
+            .ok_or(ParseError::InvalidStringEscape)?;
+        unit = unit << 4 | digit as u16;
+    }
+    Ok(unit)
+}
+
+/// The first surrogate in a WTF-8 string, for errors
+fn first_surrogate(bytes: &[u8]) -> u16 {
+    bytes
+        .windows(3)
+        .find(|seq| seq[0] == 0xED && seq[1] >= 0xA0)
+        .map_or(0, |seq| {
+            0xD000 | ((seq[1] & 0x3F) as u16) << 6 | (seq[2] & 0x3F) as u16
+        })
 }
@@ -0,0 +1,450 @@
+//! Parsing a document that has been cut off, such as one still being
+//! streamed.
+
+use std::string::String as StdString;
+use std::vec::Vec as StdVec;
+
+use bumpalo::collections::Vec;
+use bumpalo::Bump;
+use hashbrown::{BumpWrapper, HashMap};
+
+use crate::parsers::number::read_number;
+use crate::parsers::skip::skip_string_rest;
+use crate::parsers::string::{read_key, read_string_with};
+use crate::slice_iter::{CopyIter, SliceIter};
+use crate::{array_value, ignore_ws, object_value, parse_error, parse_next, MAX_NESTING};
+use crate::{JsonError, JsonResult, JsonValue, NumberError, ParseContext, ParseError, ParseState};
+
+/// What `parse_partial` made of a prefix of a document
+#[derive(Debug)]
+pub struct Partial<'bump> {
+    pub value: JsonValue<'bump>,
+    /// JSON Pointers to each value the input ended in the middle of,
+    /// innermost first. Every container around one is in the list too
+    pub incomplete: StdVec<StdString>,
+}
+
+impl<'bump> Partial<'bump> {
+    /// Whether the input was a whole document
+    pub fn is_complete(&self) -> bool {
+        self.incomplete.is_empty()
+    }
+}
+
+/// Parses a document that may have been cut off part way through, closing
+/// whatever was left open.
+///
+/// An unfinished string keeps what has been read of it, minus any unfinished
+/// escape or UTF-8 sequence. An unfinished number keeps its digits so far,
+/// and `t`, `f` and `n` up to a whole literal are read as `true`, `false` and
+/// `null`. An object member whose key or value hasn't started is left out,
+/// and so is the whole document before its first byte, which reads as
+/// `null`. Input that is invalid before the point where it ends is still an
+/// error, and so are arrays and objects nested more than 512 deep, as they
+/// would overflow the stack.
+pub fn parse_partial<'bump>(
+    json_buf: &[u8],
+    allocator: &'bump Bump,
+) -> Result<Partial<'bump>, JsonError> {
+    let mut json = SliceIter::new(json_buf);
+    let mut parser = PartialParser {
+        alloc: allocator,
+        pointer: StdString::new(),
+        depth: 0,
+        incomplete: StdVec::new(),
+    };
+    ignore_ws(&mut json);
+    let value = parser
+        .value(&mut json)
+        .map_err(|e| parse_error(e, &json_buf[json.index()..]))?;
+    let value = value.unwrap_or_else(|| {
+        parser.mark();
+        JsonValue::Null
+    });
+    Ok(Partial {
+        value,
+        incomplete: parser.incomplete,
+    })
+}
+
+struct PartialParser<'bump> {
+    alloc: &'bump Bump,
+    /// The pointer to the value being parsed
+    pointer: StdString,
+    /// How many arrays and objects it is inside of
+    depth: usize,
+    incomplete: StdVec<StdString>,
+}
+
+impl<'bump> PartialParser<'bump> {
+    /// Records the value being parsed as incomplete
+    fn mark(&mut self) {
+        self.incomplete.push(self.pointer.clone());
+    }
+
+    /// Parses the next value, or `None` if the input has ended before it
+    fn value(&mut self, json: &mut SliceIter<'_, u8>) -> JsonResult<Option<JsonValue<'bump>>> {
+        let rest = &json.as_slice()[json.index()..];
+        if let Some(value) = literal_prefix(rest) {
+            json.ignore_many(rest.len());
+            self.mark();
+            return Ok(Some(value));
+        }
+        let value = match rest.first() {
+            None => return Ok(None),
+            Some(b'[' | b'{') if self.depth == MAX_NESTING => {
+                let limit = MAX_NESTING;
+                return Err(ParseError::NestingTooDeep { limit });
+            }
+            Some(b'[') => {
+                self.depth += 1;
+                let value = self.array(json);
+                self.depth -= 1;
+                value?
+            }
+            Some(b'{') => {
+                self.depth += 1;
+                let value = self.object(json);
+                self.depth -= 1;
+                value?
+            }
+            Some(b'"') => {
+                let string = read_or_cut(json, |json| {
+                    read_string_with(json, self.alloc, ParseContext::default())
+                })?;
+                match string {
+                    Some(string) => string.into_value(self.alloc),
+                    None => self.cut_string(json)?,
+                }
+            }
+            Some(b'-' | b'0'..=b'9') => return self.number(json),
+            Some(_) => parse_next(json, self.alloc, ParseState::Value, ParseContext::default())?,
+        };
+        Ok(Some(value))
+    }
+
+    /// Reads a number, which may run to the end of the input, or `None` if
+    /// it has no digits yet
+    fn number(&mut self, json: &mut SliceIter<'_, u8>) -> JsonResult<Option<JsonValue<'bump>>> {
+        let start = json.index();
+        let end = json.as_slice().len();
+        match read_number(json) {
+            Ok(number) => {
+                // More digits could still follow
+                if json.index() == end {
+                    self.mark();
+                }
+                Ok(Some(JsonValue::Number(number)))
+            }
+            // Only a number missing its last digits is a prefix of a valid
+            // one, like `1.` or `-`, so drop whatever follows its last digit
+            Err(ParseError::InvalidNumber(
+                NumberError::MissingDigits
+                | NumberError::MissingFractionDigits
+                | NumberError::MissingExponentDigits,
+            )) if json.index() == end => {
+                let rest = &json.as_slice()[start..];
+                let Some(last) = rest.iter().rposition(u8::is_ascii_digit) else {
+                    return Ok(None);
+                };
+                let number = read_number(&mut SliceIter::new(&rest[..=last]))?;
+                self.mark();
+                Ok(Some(JsonValue::Number(number)))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Reads a string that runs to the end of the input
+    fn cut_string(&mut self, json: &mut SliceIter<'_, u8>) -> JsonResult<JsonValue<'bump>> {
+        let rest = &json.as_slice()[json.index()..];
+        let mut closed = rest[..finished_len(rest)].to_vec();
+        closed.push(b'"');
+        let string = read_string_with(
+            &mut SliceIter::new(&closed),
+            self.alloc,
+            ParseContext::default(),
+        )?;
+        json.ignore_many(rest.len());
+        self.mark();
+        Ok(string.into_value(self.alloc))
+    }
+
+    fn array(&mut self, json: &mut SliceIter<'_, u8>) -> JsonResult<JsonValue<'bump>> {
+        json.ignore_next();
+        let mut contents = Vec::new_in(self.alloc);
+        let len = self.pointer.len();
+        loop {
+            ignore_ws(json);
+            if json.peek_copy() == Some(b']') && contents.is_empty() {
+                json.ignore_next();
+                break;
+            }
+            self.pointer.push('/');
+            self.pointer.push_str(&contents.len().to_string());
+            let value = self.value(json)?;
+            self.pointer.truncate(len);
+            let Some(value) = value else {
+                self.mark();
+                break;
+            };
+            contents.push(value);
+            ignore_ws(json);
+            match json.next() {
+                Some(b',') => continue,
+                Some(b']') => break,
+                None => {
+                    self.mark();
+                    break;
+                }
+                found => {
+                    return Err(ParseError::ExpectedEndOfArray {
+                        found: found.map(|c| c as char),
+                    })
+                }
+            }
+        }
+        Ok(array_value(self.alloc, contents))
+    }
+
+    fn object(&mut self, json: &mut SliceIter<'_, u8>) -> JsonResult<JsonValue<'bump>> {
+        json.ignore_next();
+        let mut contents = HashMap::new_in(BumpWrapper(self.alloc));
+        let len = self.pointer.len();
+        loop {
+            ignore_ws(json);
+            match json.peek_copy() {
+                Some(b'}') if contents.is_empty() => {
+                    json.ignore_next();
+                    break;
+                }
+                None => {
+                    self.mark();
+                    break;
+                }
+                _ => {}
+            }
+            let key = read_or_cut(json, |json| {
+                read_key(json, self.alloc, ParseContext::default())
+            })?;
+            let Some(key) = key else {
+                json.ignore_many(json.as_slice().len() - json.index());
+                self.mark();
+                break;
+            };
+            ignore_ws(json);
+            match json.next() {
+                Some(b':') => {}
+                None => {
+                    self.mark();
+                    break;
+                }
+                found => {
+                    return Err(ParseError::ExpectedColon {
+                        found: found.map(|c| c as char),
+                    })
+                }
+            }
+            ignore_ws(json);
+            self.pointer.push('/');
+            self.pointer
+                .push_str(&key.replace('~', "~0").replace('/', "~1"));
+            let value = self.value(json)?;
+            self.pointer.truncate(len);
+            let Some(value) = value else {
+                self.mark();
+                break;
+            };
+            contents.insert(key.into_bump_str(), value);
+            ignore_ws(json);
+            match json.next() {
+                Some(b',') => continue,
+                Some(b'}') => break,
+                None => {
+                    self.mark();
+                    break;
+                }
+                found => {
+                    return Err(ParseError::ExpectedEndOfObject {
+                        found: found.map(|c| c as char),
+                    })
+                }
+            }
+        }
+        Ok(object_value(self.alloc, contents))
+    }
+}
+
+/// Reads the string at `json` with `read`, or returns `None`, leaving `json`
+/// where it was, if the input ends inside it.
+///
+/// A string is only scanned for its closing quote again if `read` fails,
+/// since an unfinished escape or UTF-8 sequence where the input ends fails
+/// the same way as an invalid one.
+fn read_or_cut<'a, T>(
+    json: &mut SliceIter<'a, u8>,
+    read: impl FnOnce(&mut SliceIter<'a, u8>) -> JsonResult<T>,
+) -> JsonResult<Option<T>> {
+    let start = json.index();
+    match read(json) {
+        Ok(string) => Ok(Some(string)),
+        Err(e) => {
+            let mut iter = SliceIter::new(json.as_slice());
+            iter.set_index(start + 1);
+            if json.as_slice().get(start) != Some(&b'"') || skip_string_rest(&mut iter).is_ok() {
+                return Err(e);
+            }
+            json.set_index(start);
+            Ok(None)
+        }
+    }
+}
+
+/// The length of the part of an unclosed string before any unfinished UTF-8
+/// sequence or escape at its end. A high surrogate escape counts as
+/// unfinished until its low surrogate is there too.
+fn finished_len(string: &[u8]) -> usize {
+    let tail = string.len().saturating_sub(3);
+    if let Some(i) = string[tail..].iter().rposition(|b| *b >= 0xC0) {
+        let lead = tail + i;
+        let len = match string[lead] {
+            0xF0.. => 4,
+            0xE0.. => 3,
+            _ => 2,
+        };
+        if string.len() - lead < len {
+            return lead;
+        }
+    }
+    // The starts of the last two escapes
+    let mut escapes = [None, None];
+    let mut i = 0;
+    while i < string.len() {
+        if string[i] == b'\\' {
+            escapes = [escapes[1], Some(i)];
+            i += 2;
+        } else {
+            i += 1;
+        }
+    }
+    let is_high_surrogate = |start: usize| match string.get(start..start + 6) {
+        Some([b'\\', b'u', hex @ ..]) => std::str::from_utf8(hex)
+            .ok()
+            .and_then(|hex| u16::from_str_radix(hex, 16).ok())
+            .map_or(false, |code| (0xD800..0xDC00).contains(&code)),
+        _ => false,
+    };
+    match escapes {
+        [_, Some(last)] if last + 6 == string.len() && is_high_surrogate(last) => last,
+        [prev, Some(last)]
+            if string[last..].len() < 6 && string.get(last + 1).map_or(true, |c| *c == b'u') =>
+        {
+            match prev {
+                Some(prev) if prev + 6 == last && is_high_surrogate(prev) => prev,
+                _ => last,
+            }
+        }
+        _ => string.len(),
+    }
+}
+
+/// The literal that `rest`, the whole rest of the input, is the start of
+fn literal_prefix<'bump>(rest: &[u8]) -> Option<JsonValue<'bump>> {
+    let literals = [
+        (&b"true"[..], JsonValue::Boolean(true)),
+        (b"false", JsonValue::Boolean(false)),
+        (b"null", JsonValue::Null),
+    ];
+    literals
+        .into_iter()
+        .find(|(literal, _)| {
+            !rest.is_empty() && rest.len() < literal.len() && literal.starts_with(rest)
+        })
+        .map(|(_, value)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn every_prefix() {
+        let json =
+            r#"{"a": [1, -2.5e3, "x\"y\u00e9\ud83d\ude00é", {"b": null}], "c": true, "d": false}"#;
+        let bump = Bump::new();
+        let whole = parse(json.as_bytes(), &bump).unwrap();
+        for end in 0..json.len() {
+            let ret = parse_partial(&json.as_bytes()[..end], &bump).unwrap();
+            assert!(!ret.is_complete(), "{end}");
+            assert_eq!(ret.incomplete.last().map(|p| p.as_str()), Some(""));
+        }
+        let ret = parse_partial(json.as_bytes(), &bump).unwrap();
+        assert!(ret.is_complete());
+        assert_eq!(ret.value, whole);
+    }
+
+    #[test]
+    fn deep() {
+        let bump = Bump::new();
+        let json = "[".repeat(MAX_NESTING);
+        let ret = parse_partial(json.as_bytes(), &bump).unwrap();
+        assert_eq!(ret.incomplete.len(), MAX_NESTING);
+        for json in [
+            &[b'['; 1_000_000][..],
+            &[b'{', b'"', b'a', b'"', b':'].repeat(1000),
+        ] {
+            assert!(matches!(
+                parse_partial(json, &bump),
+                Err(JsonError::ParseError(
+                    ParseError::NestingTooDeep { limit: MAX_NESTING },
+                    _
+                ))
+            ));
+        }
+    }
+
+    #[test]
+    fn closes() {
+        let bump = Bump::new();
+        for (json, expected, incomplete) in [
+            ("", "null", &[""][..]),
+            ("[", "[]", &[""]),
+            ("[1, 2", "[1, 2]", &["/1", ""]),
+            ("[1, 2.", "[1, 2]", &["/1", ""]),
+            ("[1, -", "[1]", &[""]),
+            ("[1.5e", "[1.5]", &["/0", ""]),
+            ("[-0", "[-0]", &["/0", ""]),
+            ("[12, 3]", "[12, 3]", &[]),
+            ("[tr", "[true]", &["/0", ""]),
+            ("[true", "[true]", &[""]),
+            ("[\"ab\\u00", "[\"ab\"]", &["/0", ""]),
+            ("[\"ab\\ud83d\\ude0", "[\"ab\"]", &["/0", ""]),
+            ("[\"ab\\", "[\"ab\"]", &["/0", ""]),
+            (
+                "{\"a\": {\"b/c\": \"x",
+                "{\"a\": {\"b/c\": \"x\"}}",
+                &["/a/b~1c", "/a", ""],
+            ),
+            ("{\"a\": 1, \"b", "{\"a\": 1}", &[""]),
+            ("{\"a\": 1, \"b\"", "{\"a\": 1}", &[""]),
+            ("{\"a\": 1, \"b\":", "{\"a\": 1}", &[""]),
+            ("{\"a\": [1]}", "{\"a\": [1]}", &[]),
+        ] {
+            let ret = parse_partial(json.as_bytes(), &bump).unwrap();
+            assert_eq!(
+                ret.value,
+                parse(expected.as_bytes(), &bump).unwrap(),
+                "{json}"
+            );
+            assert_eq!(ret.incomplete, incomplete, "{json}");
+        }
+        for json in [
+            "[1,]", "[1 2", "{\"a\" 1", "[tx", "[01", "[\"\\x", "{1", "[1.2.3", "[12-", "[1.e",
+        ] {
+            assert!(parse_partial(json.as_bytes(), &bump).is_err(), "{json}");
+        }
+        let ret = parse_partial(b"[1e400", &bump).unwrap();
+        assert_eq!(ret.value, parse(b"[1e400]", &bump).unwrap());
+    }
+}
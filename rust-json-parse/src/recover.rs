@@ -0,0 +1,481 @@
+//! A parser that carries on past errors, for reporting all of them at once.
+
+use std::fmt;
+use std::vec::Vec as StdVec;
+
+use bumpalo::collections::Vec;
+use bumpalo::Bump;
+use hashbrown::{BumpWrapper, HashMap};
+
+use crate::parsers::json5::read_identifier;
+use crate::parsers::string::read_key;
+use crate::slice_iter::{CopyIter, SliceIter};
+use crate::{array_value, encoding, object_value, parse_next, skip_ws, MAX_NESTING};
+use crate::{Dialect, JsonValue, ParseContext, ParseError, ParseOptions, ParseState};
+
+/// An error found by `parse_recovering`, at a byte offset into its input
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub error: ParseError,
+    pub offset: usize,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.error, self.offset)
+    }
+}
+
+/// What `parse_recovering` made of its input
+#[derive(Debug)]
+pub struct Recovered<'bump> {
+    /// Everything that could be parsed, with `JsonValue::Null` in place of
+    /// each value that couldn't be
+    pub value: JsonValue<'bump>,
+    /// Every error, in the order they appear in the input
+    pub diagnostics: StdVec<Diagnostic>,
+}
+
+/// Parses as much of `json_buf` as it can, recording each error instead of
+/// stopping at the first.
+///
+/// After an error, the parser skips ahead to the next comma or closing
+/// bracket at the same depth and carries on from there. A missing comma
+/// between two values, or a missing colon before a value, is reported and
+/// then parsed as if it were there. Unlike `parse`, input left over after
+/// the value is also reported. An array or object nested more than 512
+/// deep is reported and skipped, as it would overflow the stack.
+pub fn parse_recovering<'bump>(
+    json_buf: &[u8],
+    allocator: &'bump Bump,
+    options: &ParseOptions,
+) -> Recovered<'bump> {
+    let mut json = SliceIter::new(json_buf);
+    if options.skip_bom && json_buf.starts_with(encoding::UTF8_BOM) {
+        json.ignore_many(encoding::UTF8_BOM.len());
+    }
+    let mut parser = Recoverer {
+        alloc: allocator,
+        ctx: ParseContext {
+            options: *options,
+            ..Default::default()
+        },
+        diagnostics: StdVec::new(),
+        open: StdVec::new(),
+    };
+    parser.ws(&mut json);
+    let value = parser.value(&mut json);
+    parser.ws(&mut json);
+    if let Some(found) = json.peek_copy() {
+        parser.error(
+            ParseError::TrailingCharacters {
+                found: found as char,
+            },
+            json.index(),
+        );
+    }
+    Recovered {
+        value,
+        diagnostics: parser.diagnostics,
+    }
+}
+
+struct Recoverer<'bump> {
+    alloc: &'bump Bump,
+    ctx: ParseContext,
+    diagnostics: StdVec<Diagnostic>,
+    /// The closing bracket of each container being parsed, innermost last
+    open: StdVec<u8>,
+}
+
+impl<'bump> Recoverer<'bump> {
+    fn error(&mut self, error: ParseError, offset: usize) {
+        self.diagnostics.push(Diagnostic { error, offset });
+    }
+
+    /// Records an error from reading a value or key that began at `start`
+    fn error_at(&mut self, e: ParseError, start: usize, json: &SliceIter<'_, u8>) {
+        let (e, offset) = match e {
+            // The unexpected byte has been read past
+            ParseError::ExpectedNextValue { .. } | ParseError::StringQuote { .. } => (e, start),
+            // A short bad literal near the end, like `x]`
+            ParseError::UnexpectedEndOfFile if json.index() == start => {
+                let found = json.peek_copy().map(|c| c as char);
+                (ParseError::ExpectedNextValue { found }, start)
+            }
+            ParseError::ControlCharacter { .. } => (e, json.index() - 1),
+            e => (e, json.index()),
+        };
+        self.error(e, offset);
+    }
+
+    /// Records that the container ending with `close` wasn't closed, having
+    /// found something else at `json`
+    fn unclosed(&mut self, json: &SliceIter<'_, u8>, close: u8) {
+        let found = json.peek_copy().map(|c| c as char);
+        let e = match close {
+            b']' => ParseError::ExpectedEndOfArray { found },
+            _ => ParseError::ExpectedEndOfObject { found },
+        };
+        self.error(e, json.index());
+    }
+
+    /// Ends the innermost container at the wrong kind of closing bracket,
+    /// which is left for an outer container if it can close one, and is
+    /// otherwise taken as the right kind
+    fn mismatched(&mut self, json: &mut SliceIter<'_, u8>) {
+        let outer = &self.open[..self.open.len() - 1];
+        if !json.peek_copy().map_or(false, |c| outer.contains(&c)) {
+            json.ignore_next();
+        }
+    }
+
+    /// Skips whitespace, and after an unclosed comment, everything
+    fn ws(&mut self, json: &mut SliceIter<'_, u8>) {
+        if let Err(e) = skip_ws(json, self.ctx) {
+            self.error(e, json.index());
+            json.set_index(json.as_slice().len());
+        }
+    }
+
+    fn value(&mut self, json: &mut SliceIter<'_, u8>) -> JsonValue<'bump> {
+        let start = json.index();
+        match json.peek_copy() {
+            Some(b'[' | b'{') if self.open.len() == MAX_NESTING => {
+                let limit = MAX_NESTING;
+                self.error(ParseError::NestingTooDeep { limit }, start);
+                resync(json);
+                return JsonValue::Null;
+            }
+            Some(b'[') => {
+                self.open.push(b']');
+                let value = self.array(json);
+                self.open.pop();
+                return value;
+            }
+            Some(b'{') => {
+                self.open.push(b'}');
+                let value = self.object(json);
+                self.open.pop();
+                return value;
+            }
+            None => {
+                self.error(ParseError::UnexpectedEndOfFile, start);
+                return JsonValue::Null;
+            }
+            Some(_) => {}
+        }
+        match parse_next(json, self.alloc, ParseState::Value, self.ctx) {
+            Ok(value) => value,
+            Err(e) => {
+                self.error_at(e, start, json);
+                json.set_index(start);
+                resync(json);
+                JsonValue::Null
+            }
+        }
+    }
+
+    fn array(&mut self, json: &mut SliceIter<'_, u8>) -> JsonValue<'bump> {
+        json.ignore_next();
+        let mut contents = Vec::new_in(self.alloc);
+        let mut after_comma = false;
+        loop {
+            self.ws(json);
+            match json.peek_copy() {
+                Some(b']') => {
+                    self.trailing_comma(json, after_comma);
+                    json.ignore_next();
+                    break;
+                }
+                Some(b'}') | None => {
+                    self.unclosed(json, b']');
+                    self.mismatched(json);
+                    break;
+                }
+                Some(_) => {}
+            }
+            contents.push(self.value(json));
+            self.ws(json);
+            match self.separator(json, b']') {
+                Some(comma) => after_comma = comma,
+                None => break,
+            }
+        }
+        array_value(self.alloc, contents)
+    }
+
+    fn object(&mut self, json: &mut SliceIter<'_, u8>) -> JsonValue<'bump> {
+        json.ignore_next();
+        let mut contents = HashMap::new_in(BumpWrapper(self.alloc));
+        let mut after_comma = false;
+        loop {
+            self.ws(json);
+            let start = json.index();
+            let key = match json.peek_copy() {
+                Some(b'}') => {
+                    self.trailing_comma(json, after_comma);
+                    json.ignore_next();
+                    break;
+                }
+                Some(b']') | None => {
+                    self.unclosed(json, b'}');
+                    self.mismatched(json);
+                    break;
+                }
+                Some(b'"' | b'\'') => read_key(json, self.alloc, self.ctx),
+                Some(_) if self.ctx.options.dialect == Dialect::Json5 => {
+                    read_identifier(json, self.alloc)
+                }
+                Some(_) => read_key(json, self.alloc, self.ctx),
+            };
+            let key = match key {
+                Ok(key) => key,
+                // Drop the whole member
+                Err(e) => {
+                    self.error_at(e, start, json);
+                    json.set_index(start);
+                    resync(json);
+                    match self.separator(json, b'}') {
+                        Some(comma) => after_comma = comma,
+                        None => break,
+                    }
+                    continue;
+                }
+            };
+            self.ws(json);
+            match json.peek_copy() {
+                Some(b':') => json.ignore_next(),
+                found => {
+                    self.error(
+                        ParseError::ExpectedColon {
+                            found: found.map(|c| c as char),
+                        },
+                        json.index(),
+                    );
+                    if !found.map_or(false, |c| self.starts_value(c)) {
+                        resync(json);
+                        match self.separator(json, b'}') {
+                            Some(comma) => after_comma = comma,
+                            None => break,
+                        }
+                        continue;
+                    }
+                }
+            }
+            self.ws(json);
+            let value = self.value(json);
+            contents.insert(key.into_bump_str(), value);
+            self.ws(json);
+            match self.separator(json, b'}') {
+                Some(comma) => after_comma = comma,
+                None => break,
+            }
+        }
+        object_value(self.alloc, contents)
+    }
+
+    /// Reports a comma just before `json`'s closing bracket, if it isn't allowed
+    fn trailing_comma(&mut self, json: &SliceIter<'_, u8>, after_comma: bool) {
+        if after_comma && !self.ctx.trailing_commas() {
+            let found = json.peek_copy().map(|c| c as char);
+            self.error(ParseError::ExpectedNextValue { found }, json.index());
+        }
+    }
+
+    /// Reads what follows a value in a container that ends with `close`:
+    /// `None` at the end of the container, otherwise whether there was a
+    /// comma, or two values with nothing between them, which are parsed as
+    /// if there were one
+    fn separator(&mut self, json: &mut SliceIter<'_, u8>, close: u8) -> Option<bool> {
+        match json.peek_copy() {
+            Some(b',') => {
+                json.ignore_next();
+                return Some(true);
+            }
+            Some(c) if c == close => {
+                json.ignore_next();
+                return None;
+            }
+            _ => {}
+        }
+        self.unclosed(json, close);
+        match json.peek_copy() {
+            Some(b']' | b'}') => {
+                self.mismatched(json);
+                None
+            }
+            Some(c) if self.starts_value(c) => Some(false),
+            Some(_) => {
+                resync(json);
+                self.separator_after_resync(json, close)
+            }
+            None => None,
+        }
+    }
+
+    /// Like `separator`, where only a comma, closing bracket or the end can
+    /// follow
+    fn separator_after_resync(&mut self, json: &mut SliceIter<'_, u8>, close: u8) -> Option<bool> {
+        match json.peek_copy() {
+            Some(b',') => {
+                json.ignore_next();
+                Some(true)
+            }
+            Some(c) if c == close => {
+                json.ignore_next();
+                None
+            }
+            Some(_) => {
+                self.unclosed(json, close);
+                self.mismatched(json);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn starts_value(&self, c: u8) -> bool {
+        let json5 = self.ctx.options.dialect == Dialect::Json5;
+        matches!(
+            c,
+            b'"' | b'[' | b'{' | b'-' | b'0'..=b'9' | b't' | b'f' | b'n'
+        ) || json5 && matches!(c, b'\'' | b'+' | b'.' | b'I' | b'N')
+    }
+}
+
+/// Skips to the next comma or closing bracket that isn't nested inside
+/// something else, or inside a string.
+///
+/// Strings can't contain a raw line break, so one also ends a string, which
+/// stops a missing quote from swallowing the rest of the input.
+fn resync(json: &mut SliceIter<'_, u8>) {
+    let mut depth: usize = 0;
+    while let Some(c) = json.peek_copy() {
+        match c {
+            b',' | b']' | b'}' if depth == 0 => return,
+            b']' | b'}' => depth -= 1,
+            b'[' | b'{' => depth += 1,
+            b'"' | b'\'' => {
+                json.ignore_next();
+                loop {
+                    match json.next() {
+                        Some(q) if q == c => break,
+                        Some(b'\\') => json.ignore_next(),
+                        Some(b'\n') | None => break,
+                        Some(_) => {}
+                    }
+                }
+                continue;
+            }
+            _ => {}
+        }
+        json.ignore_next();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn check(json: &str, expected: &str, errors: &[usize]) {
+        let bump = Bump::new();
+        let ret = parse_recovering(json.as_bytes(), &bump, &ParseOptions::default());
+        assert_eq!(
+            ret.value,
+            parse(expected.as_bytes(), &bump).unwrap(),
+            "{json}"
+        );
+        let offsets: StdVec<usize> = ret.diagnostics.iter().map(|d| d.offset).collect();
+        assert_eq!(offsets, errors, "{json}: {:?}", ret.diagnostics);
+    }
+
+    #[test]
+    fn valid() {
+        let food = include_bytes!("../../tests/food.json");
+        let bump = Bump::new();
+        let ret = parse_recovering(food, &bump, &ParseOptions::default());
+        assert!(ret.diagnostics.is_empty());
+        assert_eq!(ret.value, parse(food, &bump).unwrap());
+    }
+
+    #[test]
+    fn every_prefix() {
+        let json = br#"{"a": [1, -2.5e3, "x\"y", {"b": null}], "c": true}"#;
+        let bump = Bump::new();
+        for end in 0..json.len() {
+            let ret = parse_recovering(&json[..end], &bump, &ParseOptions::default());
+            assert!(!ret.diagnostics.is_empty(), "{end}");
+        }
+        for byte in 0..json.len() {
+            let mut json = json.to_vec();
+            json[byte] = b'@';
+            parse_recovering(&json, &bump, &ParseOptions::default());
+        }
+    }
+
+    #[test]
+    fn recovers() {
+        check("[1,,2]", "[1, null, 2]", &[3]);
+        check("[1 2, tru, 3]", "[1, 2, null, 3]", &[3, 6]);
+        check("[1,]", "[1]", &[3]);
+        check("[1, \"a\\q\", [2, x]]", "[1, null, [2, null]]", &[8, 15]);
+        check(
+            r#"{"a": 1 "b": 2, "c" 3, "d", "e": 4}"#,
+            r#"{"a": 1, "b": 2, "c": 3, "e": 4}"#,
+            &[8, 20, 26],
+        );
+        check(
+            r#"{"a": 01, 5: 1, "b": [1}"#,
+            r#"{"a": null, "b": [1]}"#,
+            &[7, 10, 23],
+        );
+        check("[\"abc\n, 1]", "[null, 1]", &[5]);
+        check(r#"{"a": [1, 2"#, r#"{"a": [1, 2]}"#, &[11, 11]);
+        check("[1] 2", "[1]", &[4]);
+        check("", "null", &[0]);
+        check(
+            "[1, @#, {\"x\": [} 2]",
+            "[1, null, {\"x\": []}, 2]",
+            &[4, 15, 17],
+        );
+    }
+
+    #[test]
+    fn deep() {
+        let bump = Bump::new();
+        let nested = |depth: usize| "[".repeat(depth) + &"]".repeat(depth);
+        let ret = parse_recovering(nested(MAX_NESTING).as_bytes(), &bump, &Default::default());
+        assert!(ret.diagnostics.is_empty());
+        let json = format!("[{}, 1]", nested(MAX_NESTING));
+        let ret = parse_recovering(json.as_bytes(), &bump, &ParseOptions::default());
+        assert_eq!(
+            format!("{:?}", ret.diagnostics),
+            format!("[Diagnostic {{ error: NestingTooDeep {{ limit: {MAX_NESTING} }}, offset: {MAX_NESTING} }}]")
+        );
+        let ret = parse_recovering(&[b'['; 1_000_000], &bump, &ParseOptions::default());
+        assert_eq!(ret.diagnostics.len(), MAX_NESTING + 1);
+    }
+
+    #[test]
+    fn options() {
+        let bump = Bump::new();
+        let options = ParseOptions {
+            comments: true,
+            trailing_commas: true,
+            ..Default::default()
+        };
+        let ret = parse_recovering(b"[1, /* two */ 2,] /* end", &bump, &options);
+        assert_eq!(ret.value, parse(b"[1, 2]", &bump).unwrap());
+        assert_eq!(ret.diagnostics.len(), 1);
+        assert!(matches!(
+            ret.diagnostics[0].error,
+            ParseError::UnterminatedComment
+        ));
+        assert_eq!(
+            ret.diagnostics[0].to_string(),
+            "Comment was never closed with `*/` at byte 18"
+        );
+    }
+}
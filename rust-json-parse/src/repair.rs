@@ -0,0 +1,360 @@
+//! Fixing the common ways JSON-like data from other tools goes wrong.
+
+use std::vec::Vec as StdVec;
+
+use bumpalo::Bump;
+
+use crate::{parse, JsonError, JsonValue};
+
+/// A kind of fix made by `repair`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RepairKind {
+    /// `True`, `False` or `None` written as `true`, `false` or `null`
+    PythonLiteral,
+    /// A single-quoted string written with double quotes
+    SingleQuotes,
+    /// An unquoted object key put in quotes
+    UnquotedKey,
+    /// A comma added between two values, straight after the first
+    MissingComma,
+    /// A comma dropped before a closing bracket
+    TrailingComma,
+}
+
+/// One fix, at the byte offset in the original input that it was made at.
+///
+/// That is where the fixed token starts, except for `MissingComma`, whose
+/// offset is where the value before the comma ends.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Repair {
+    pub kind: RepairKind,
+    pub offset: usize,
+}
+
+/// The output of `repair`
+#[derive(Clone, Debug, Default)]
+pub struct Repaired {
+    pub json: StdVec<u8>,
+    /// Every fix made, in the order of the input
+    pub repairs: StdVec<Repair>,
+}
+
+impl Repaired {
+    pub fn parse<'bump>(&self, allocator: &'bump Bump) -> Result<JsonValue<'bump>, JsonError> {
+        parse(&self.json, allocator)
+    }
+}
+
+/// Rewrites JSON-ish input into JSON, fixing Python's `True`, `False` and
+/// `None`, single-quoted strings, unquoted keys, missing commas between
+/// values and trailing commas.
+///
+/// Only those problems are fixed, and everything else is copied as it is, so
+/// the output can still fail to parse.
+pub fn repair(json: &[u8]) -> Repaired {
+    let mut repairer = Repairer {
+        json,
+        i: 0,
+        out: StdVec::with_capacity(json.len() + json.len() / 16),
+        repairs: StdVec::new(),
+        stack: StdVec::new(),
+        expect: Expect::Value,
+        gap: None,
+    };
+    repairer.run();
+    Repaired {
+        json: repairer.out,
+        repairs: repairer.repairs,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Expect {
+    Key,
+    Colon,
+    Value,
+    /// A comma or closing bracket, after a value
+    Next,
+}
+
+struct Repairer<'a> {
+    json: &'a [u8],
+    i: usize,
+    out: StdVec<u8>,
+    repairs: StdVec<Repair>,
+    /// Whether each open container is an object, innermost last
+    stack: StdVec<bool>,
+    expect: Expect,
+    /// Where the bytes after the last value start in `json`, while they are
+    /// held back so that a missing comma can go before them
+    gap: Option<usize>,
+}
+
+impl<'a> Repairer<'a> {
+    fn fix(&mut self, kind: RepairKind) {
+        self.repairs.push(Repair {
+            kind,
+            offset: self.i,
+        });
+    }
+
+    fn run(&mut self) {
+        while let Some(&c) = self.json.get(self.i) {
+            match c {
+                b'"' | b'\'' | b'[' | b'{' | b'-' | b'0'..=b'9' => self.value_start(),
+                b'a'..=b'z' | b'A'..=b'Z' | b'_' | b'$' | 0x80.. => self.value_start(),
+                b']' | b'}' => {
+                    self.flush_gap();
+                    self.copy(1);
+                    self.stack.pop();
+                    self.value_done();
+                }
+                b':' => {
+                    self.flush_gap();
+                    self.copy(1);
+                    self.expect = Expect::Value;
+                }
+                b',' => {
+                    self.flush_gap();
+                    let next = self.json[self.i + 1..]
+                        .iter()
+                        .find(|c| !c.is_ascii_whitespace());
+                    if matches!(next, Some(b']' | b'}')) {
+                        self.fix(RepairKind::TrailingComma);
+                        self.i += 1;
+                        continue;
+                    }
+                    self.copy(1);
+                    self.expect = match self.stack.last() {
+                        Some(true) => Expect::Key,
+                        _ => Expect::Value,
+                    };
+                }
+                _ if self.expect == Expect::Next => {
+                    self.gap.get_or_insert(self.i);
+                    self.i += 1;
+                }
+                _ => self.copy(1),
+            }
+        }
+        self.flush_gap();
+    }
+
+    fn flush_gap(&mut self) {
+        if let Some(start) = self.gap.take() {
+            self.out.extend_from_slice(&self.json[start..self.i]);
+        }
+    }
+
+    fn copy(&mut self, len: usize) {
+        let end = (self.i + len).min(self.json.len());
+        self.out.extend_from_slice(&self.json[self.i..end]);
+        self.i = end;
+    }
+
+    fn value_done(&mut self) {
+        self.expect = Expect::Next;
+    }
+
+    /// Handles the first byte of a key or value
+    fn value_start(&mut self) {
+        if self.expect == Expect::Next && !self.stack.is_empty() {
+            self.repairs.push(Repair {
+                kind: RepairKind::MissingComma,
+                offset: self.gap.unwrap_or(self.i),
+            });
+            self.out.push(b',');
+            self.expect = match self.stack.last() {
+                Some(true) => Expect::Key,
+                _ => Expect::Value,
+            };
+        }
+        self.flush_gap();
+        let c = self.json[self.i];
+        if self.expect == Expect::Key {
+            match c {
+                b'"' | b'\'' => self.string(),
+                _ if is_word(c) => {
+                    self.fix(RepairKind::UnquotedKey);
+                    let len = self.word_len();
+                    self.out.push(b'"');
+                    self.copy(len);
+                    self.out.push(b'"');
+                }
+                _ => {
+                    self.copy(1);
+                    return;
+                }
+            }
+            self.expect = Expect::Colon;
+            return;
+        }
+        match c {
+            b'"' | b'\'' => self.string(),
+            b'[' | b'{' => {
+                self.copy(1);
+                self.stack.push(c == b'{');
+                self.expect = if c == b'{' {
+                    Expect::Key
+                } else {
+                    Expect::Value
+                };
+                return;
+            }
+            b'-' | b'0'..=b'9' => {
+                let len = self.json[self.i..]
+                    .iter()
+                    .take_while(|c| matches!(c, b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E'))
+                    .count();
+                self.copy(len);
+            }
+            _ => {
+                let len = self.word_len();
+                let literal: Option<&[u8]> = match &self.json[self.i..self.i + len] {
+                    b"True" => Some(b"true"),
+                    b"False" => Some(b"false"),
+                    b"None" => Some(b"null"),
+                    _ => None,
+                };
+                match literal {
+                    Some(literal) => {
+                        self.fix(RepairKind::PythonLiteral);
+                        self.out.extend_from_slice(literal);
+                        self.i += len;
+                    }
+                    None => self.copy(len),
+                }
+            }
+        }
+        self.value_done();
+    }
+
+    fn word_len(&self) -> usize {
+        self.json[self.i..]
+            .iter()
+            .take_while(|c| is_word(**c))
+            .count()
+    }
+
+    /// Copies a string, rewriting it with double quotes if it has single ones
+    fn string(&mut self) {
+        let quote = self.json[self.i];
+        if quote == b'\'' {
+            self.fix(RepairKind::SingleQuotes);
+        }
+        self.out.push(b'"');
+        self.i += 1;
+        while let Some(&c) = self.json.get(self.i) {
+            match c {
+                b'\\' if quote == b'\'' && self.json.get(self.i + 1) == Some(&b'\'') => {
+                    self.out.push(b'\'');
+                    self.i += 2;
+                }
+                b'\\' => self.copy(2),
+                b'"' if quote == b'\'' => {
+                    self.out.extend_from_slice(b"\\\"");
+                    self.i += 1;
+                }
+                _ if c == quote => {
+                    self.out.push(b'"');
+                    self.i += 1;
+                    return;
+                }
+                _ => self.copy(1),
+            }
+        }
+    }
+}
+
+fn is_word(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, b'_' | b'$') || c >= 0x80
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(json: &str, expected: &str, fixes: &[(RepairKind, usize)]) {
+        let repaired = repair(json.as_bytes());
+        assert_eq!(std::str::from_utf8(&repaired.json).unwrap(), expected);
+        let repairs: StdVec<_> = repaired
+            .repairs
+            .iter()
+            .map(|repair| (repair.kind, repair.offset))
+            .collect();
+        assert_eq!(repairs, fixes, "{json}");
+        let bump = Bump::new();
+        repaired.parse(&bump).unwrap();
+    }
+
+    #[test]
+    fn valid_is_unchanged() {
+        let food = include_bytes!("../../tests/food.json");
+        let repaired = repair(food);
+        assert_eq!(repaired.json, food);
+        assert!(repaired.repairs.is_empty());
+    }
+
+    #[test]
+    fn repairs() {
+        use RepairKind::*;
+        check(
+            "{'a': True, 'b': [None, False]}",
+            r#"{"a": true, "b": [null, false]}"#,
+            &[
+                (SingleQuotes, 1),
+                (PythonLiteral, 6),
+                (SingleQuotes, 12),
+                (PythonLiteral, 18),
+                (PythonLiteral, 24),
+            ],
+        );
+        check(
+            r#"{'it\'s': 'say "hi"'}"#,
+            r#"{"it's": "say \"hi\""}"#,
+            &[(SingleQuotes, 1), (SingleQuotes, 10)],
+        );
+        check(
+            "{name: 1, $x_2: {é: null}}",
+            r#"{"name": 1, "$x_2": {"é": null}}"#,
+            &[(UnquotedKey, 1), (UnquotedKey, 10), (UnquotedKey, 17)],
+        );
+        check(
+            "[1 2\n\"a\" {\"b\": 1 \"c\": 2} [3]]",
+            "[1, 2,\n\"a\", {\"b\": 1, \"c\": 2}, [3]]",
+            &[
+                (MissingComma, 2),
+                (MissingComma, 4),
+                (MissingComma, 8),
+                (MissingComma, 16),
+                (MissingComma, 24),
+            ],
+        );
+        check(r#"["a""b"]"#, r#"["a","b"]"#, &[(MissingComma, 4)]);
+        check(
+            "{\"a\": [1, 2, ], }",
+            "{\"a\": [1, 2 ] }",
+            &[(TrailingComma, 11), (TrailingComma, 14)],
+        );
+        check("[true, null, -1.5e3]", "[true, null, -1.5e3]", &[]);
+    }
+
+    #[test]
+    fn many_missing_commas() {
+        let json = format!("[{}0]", "0 ".repeat(200_000));
+        let repaired = repair(json.as_bytes());
+        assert_eq!(
+            repaired.json,
+            format!("[{}0]", "0, ".repeat(200_000)).as_bytes()
+        );
+        assert_eq!(repaired.repairs.len(), 200_000);
+    }
+
+    #[test]
+    fn unrepairable() {
+        let bump = Bump::new();
+        let repaired = repair(b"[1, @]");
+        assert_eq!(repaired.json, b"[1, @]");
+        assert!(repaired.parse(&bump).is_err());
+    }
+}
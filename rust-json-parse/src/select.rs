@@ -0,0 +1,305 @@
+//! Parsing only the parts of a document at given paths.
+
+use std::string::String as StdString;
+
+use bumpalo::collections::Vec;
+use bumpalo::Bump;
+use hashbrown::{BumpWrapper, HashMap};
+
+use crate::parsers::skip::skip_value;
+use crate::parsers::string::{read_borrowed_str, read_string};
+use crate::slice_iter::{CopyIter, SliceIter};
+use crate::{ignore_ws, parse_error, parse_next};
+use crate::{JsonError, JsonResult, JsonValue, ParseContext, ParseError, ParseState};
+
+#[derive(thiserror::Error, Debug)]
+pub enum SelectorError {
+    #[error("JSON Pointer {0:?} has to be empty or start with `/`")]
+    InvalidPointer(StdString),
+}
+
+/// A set of paths to parse out of documents, skipping everything else.
+///
+/// Paths are JSON Pointers like `/user/id`, where a `*` segment matches every
+/// key of an object or element of an array, as in `/items/*/sku`. Compile a
+/// `Selector` once and reuse it for each document.
+#[derive(Clone, Debug, Default)]
+pub struct Selector {
+    root: Selection,
+}
+
+/// Where the selected paths go from one value
+#[derive(Clone, Debug, Default)]
+struct Selection {
+    /// A path ends here, so the whole value is parsed
+    whole: bool,
+    children: HashMap<StdString, Selection>,
+    /// From a `*` segment
+    any: Option<Box<Selection>>,
+}
+
+impl Selector {
+    pub fn new(paths: &[&str]) -> Result<Self, SelectorError> {
+        let mut root = Selection::default();
+        for path in paths {
+            let segments: std::vec::Vec<StdString> = match path.strip_prefix('/') {
+                Some(rest) => rest
+                    .split('/')
+                    .map(|segment| segment.replace("~1", "/").replace("~0", "~"))
+                    .collect(),
+                None if path.is_empty() => std::vec::Vec::new(),
+                None => return Err(SelectorError::InvalidPointer(path.to_string())),
+            };
+            root.insert(&segments);
+        }
+        root.spread_wildcards();
+        Ok(Self { root })
+    }
+
+    /// Parses the selected paths out of `json_buf`.
+    ///
+    /// Objects on the way to a selected value only have the keys that lead to
+    /// one, and arrays keep their length, with `JsonValue::Null` in place of
+    /// each element that isn't selected. A path that isn't in the document is
+    /// left out. Skipped values are scanned past without being validated.
+    pub fn parse<'bump>(
+        &self,
+        json_buf: &[u8],
+        allocator: &'bump Bump,
+    ) -> Result<JsonValue<'bump>, JsonError> {
+        let mut json = SliceIter::new(json_buf);
+        ignore_ws(&mut json);
+        if json.peek_copy().is_none() {
+            return Ok(JsonValue::Null);
+        }
+        let value = select(&mut json, allocator, &self.root)
+            .map_err(|e| parse_error(e, &json_buf[json.index()..]))?;
+        Ok(value.unwrap_or(JsonValue::Null))
+    }
+}
+
+impl Selection {
+    fn insert(&mut self, segments: &[StdString]) {
+        match segments.split_first() {
+            None => self.whole = true,
+            Some((segment, rest)) if segment == "*" => {
+                self.any.get_or_insert_with(Default::default).insert(rest)
+            }
+            Some((segment, rest)) => self
+                .children
+                .entry(segment.clone())
+                .or_default()
+                .insert(rest),
+        }
+    }
+
+    /// Adds what `*` selects to each named child too, so a key only ever
+    /// needs looking up once
+    fn spread_wildcards(&mut self) {
+        if let Some(any) = &self.any {
+            let any = (**any).clone();
+            for child in self.children.values_mut() {
+                child.merge(&any);
+            }
+        }
+        for child in self.children.values_mut() {
+            child.spread_wildcards();
+        }
+        if let Some(any) = &mut self.any {
+            any.spread_wildcards();
+        }
+    }
+
+    fn merge(&mut self, other: &Selection) {
+        self.whole |= other.whole;
+        for (key, child) in &other.children {
+            self.children.entry(key.clone()).or_default().merge(child);
+        }
+        if let Some(any) = &other.any {
+            self.any.get_or_insert_with(Default::default).merge(any);
+        }
+    }
+
+    fn child(&self, key: &str) -> Option<&Selection> {
+        self.children.get(key).or(self.any.as_deref())
+    }
+
+    fn element(&self, index: usize) -> Option<&Selection> {
+        if self.children.is_empty() {
+            return self.any.as_deref();
+        }
+        self.child(&index.to_string())
+    }
+}
+
+/// Parses what `selection` selects of the next value, or `None` if it
+/// selects nothing there
+fn select<'bump>(
+    json: &mut SliceIter<'_, u8>,
+    alloc: &'bump Bump,
+    selection: &Selection,
+) -> JsonResult<Option<JsonValue<'bump>>> {
+    if selection.whole {
+        return parse_next(json, alloc, ParseState::Value, ParseContext::default()).map(Some);
+    }
+    match json.peek_copy() {
+        Some(b'{') => select_object(json, alloc, selection).map(Some),
+        Some(b'[') => select_array(json, alloc, selection).map(Some),
+        Some(_) => {
+            skip_value(json)?;
+            Ok(None)
+        }
+        None => Err(ParseError::UnexpectedEndOfFile),
+    }
+}
+
+fn select_object<'bump>(
+    json: &mut SliceIter<'_, u8>,
+    alloc: &'bump Bump,
+    selection: &Selection,
+) -> JsonResult<JsonValue<'bump>> {
+    json.ignore_next();
+    let mut contents = HashMap::new_in(BumpWrapper(alloc));
+    ignore_ws(json);
+    if json.peek_copy() == Some(b'}') {
+        json.ignore_next();
+        return Ok(JsonValue::Object(alloc.alloc(contents)));
+    }
+    loop {
+        ignore_ws(json);
+        // Keys are only copied if they are selected, or have escapes
+        let key: &str = match read_borrowed_str(json)? {
+            Some(key) => key,
+            None => alloc.alloc(read_string(json, alloc)?).as_str(),
+        };
+        ignore_ws(json);
+        let c = json.next();
+        if c != Some(b':') {
+            return Err(ParseError::ExpectedColon {
+                found: c.map(|c| c as char),
+            });
+        }
+        ignore_ws(json);
+        match selection.child(key) {
+            Some(child) => {
+                if let Some(value) = select(json, alloc, child)? {
+                    contents.insert(alloc.alloc_str(key), value);
+                }
+            }
+            None => skip_value(json)?,
+        }
+        ignore_ws(json);
+        match json.next() {
+            Some(b',') => continue,
+            Some(b'}') => break,
+            found => {
+                return Err(ParseError::ExpectedEndOfObject {
+                    found: found.map(|c| c as char),
+                })
+            }
+        }
+    }
+    Ok(JsonValue::Object(alloc.alloc(contents)))
+}
+
+fn select_array<'bump>(
+    json: &mut SliceIter<'_, u8>,
+    alloc: &'bump Bump,
+    selection: &Selection,
+) -> JsonResult<JsonValue<'bump>> {
+    json.ignore_next();
+    let mut contents = Vec::new_in(alloc);
+    ignore_ws(json);
+    if json.peek_copy() == Some(b']') {
+        json.ignore_next();
+        return Ok(JsonValue::Array(alloc.alloc(contents)));
+    }
+    loop {
+        ignore_ws(json);
+        let value = match selection.element(contents.len()) {
+            Some(child) => select(json, alloc, child)?,
+            None => {
+                skip_value(json)?;
+                None
+            }
+        };
+        contents.push(value.unwrap_or(JsonValue::Null));
+        ignore_ws(json);
+        match json.next() {
+            Some(b',') => continue,
+            Some(b']') => break,
+            found => {
+                return Err(ParseError::ExpectedEndOfArray {
+                    found: found.map(|c| c as char),
+                })
+            }
+        }
+    }
+    Ok(JsonValue::Array(alloc.alloc(contents)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    const EVENT: &str = r#"{
+        "user": {"id": 7, "name": "ann", "tags": ["a", "b"]},
+        "items": [
+            {"sku": "x1", "qty": 2, "meta": {"deep": [1, 2, 3]}},
+            {"qty": 1},
+            {"sku": "x\"3", "qty": 5}
+        ],
+        "a/b": {"~": 1, "c": 2},
+        "blob": "skipped \" }"
+    }"#;
+
+    fn check(paths: &[&str], expected: &str) {
+        let bump = Bump::new();
+        let selector = Selector::new(paths).unwrap();
+        let value = selector.parse(EVENT.as_bytes(), &bump).unwrap();
+        assert_eq!(
+            value,
+            parse(expected.as_bytes(), &bump).unwrap(),
+            "{paths:?}"
+        );
+    }
+
+    #[test]
+    fn selects() {
+        check(&["/user/id"], r#"{"user": {"id": 7}}"#);
+        check(
+            &["/items/*/sku", "/user/id"],
+            r#"{"user": {"id": 7}, "items": [{"sku": "x1"}, {}, {"sku": "x\"3"}]}"#,
+        );
+        check(
+            &["/items/0/meta", "/items/*/qty"],
+            r#"{"items": [{"qty": 2, "meta": {"deep": [1, 2, 3]}}, {"qty": 1}, {"qty": 5}]}"#,
+        );
+        check(&["/items/1"], r#"{"items": [null, {"qty": 1}, null]}"#);
+        check(&["/a~1b/~0"], r#"{"a/b": {"~": 1}}"#);
+        check(
+            &["/user/*"],
+            r#"{"user": {"id": 7, "name": "ann", "tags": ["a", "b"]}}"#,
+        );
+        check(&["/user/name/first", "/missing"], r#"{"user": {}}"#);
+        check(&[""], EVENT);
+        check(&[], "{}");
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            Selector::new(&["user"]),
+            Err(SelectorError::InvalidPointer(_))
+        ));
+        let bump = Bump::new();
+        let selector = Selector::new(&["/a"]).unwrap();
+        assert!(selector.parse(br#"{"a": [1,}"#, &bump).is_err());
+        assert!(selector.parse(br#"{"a" 1}"#, &bump).is_err());
+        assert!(selector.parse(br#"{"b": 1 "a": 2}"#, &bump).is_err());
+        // Skipped values aren't validated
+        assert!(selector.parse(br#"{"b": [tru], "a": 1}"#, &bump).is_ok());
+        assert_eq!(selector.parse(b"", &bump).unwrap(), JsonValue::Null);
+    }
+}
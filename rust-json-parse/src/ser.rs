@@ -0,0 +1,715 @@
+//! Serde support for writing a `JsonValue` out with any format, and for
+//! building a `JsonValue` from any `Serialize` type.
+
+use std::fmt::Display;
+use std::string::String as StdString;
+
+use bumpalo::collections::{String, Vec};
+use bumpalo::Bump;
+use hashbrown::{BumpWrapper, HashMap};
+use serde::ser::{self, Impossible, Serialize, SerializeMap, SerializeSeq, Serializer};
+
+use crate::{JsonDocument, JsonObject, JsonValue};
+
+#[derive(thiserror::Error, Debug)]
+pub enum SerError {
+    #[error("Object keys must be strings or integers, found {found}")]
+    InvalidKey { found: &'static str },
+    /// `JsonValue` numbers are `f64`s, which only hold integers up to 2^53
+    /// exactly, so larger ones are an error instead of being rounded
+    #[error("Integer {0} can't be stored exactly as an f64")]
+    InexactInteger(i128),
+    #[error("{0}")]
+    Custom(StdString),
+}
+
+impl ser::Error for SerError {
+    fn custom<T: Display>(msg: T) -> Self {
+        SerError::Custom(msg.to_string())
+    }
+}
+
+impl<'bump> Serialize for JsonValue<'bump> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            JsonValue::Object(obj) => {
+                let mut map = serializer.serialize_map(Some(obj.len()))?;
+                for (key, value) in obj.iter() {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+            JsonValue::Array(arr) => {
+                let mut seq = serializer.serialize_seq(Some(arr.len()))?;
+                for value in arr.iter() {
+                    seq.serialize_element(value)?;
+                }
+                seq.end()
+            }
+            JsonValue::String(str) => serializer.serialize_str(str),
+            JsonValue::Str(str) => serializer.serialize_str(str),
+            JsonValue::Wtf8(bytes) => serializer.serialize_bytes(bytes),
+            // Whole numbers are written as integers, so `3` doesn't come back out as `3.0`
+            JsonValue::Number(num) if num.fract() == 0.0 => {
+                if (0.0..18446744073709551616.0).contains(num) {
+                    serializer.serialize_u64(*num as u64)
+                } else if (-9223372036854775808.0..0.0).contains(num) {
+                    serializer.serialize_i64(*num as i64)
+                } else {
+                    serializer.serialize_f64(*num)
+                }
+            }
+            JsonValue::Number(num) => serializer.serialize_f64(*num),
+            JsonValue::Boolean(bool) => serializer.serialize_bool(*bool),
+            JsonValue::Null => serializer.serialize_unit(),
+        }
+    }
+}
+
+impl Serialize for JsonDocument {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.root().serialize(serializer)
+    }
+}
+
+/// Converts any `Serialize` type into a tree allocated in `bump`.
+///
+/// Non-finite floats become `null`, and integer map keys become strings, as
+/// there is no way to write either in JSON.
+pub fn to_value_in<'bump, T: Serialize + ?Sized>(
+    value: &T,
+    bump: &'bump Bump,
+) -> Result<JsonValue<'bump>, SerError> {
+    value.serialize(ValueSerializer { bump })
+}
+
+#[derive(Clone, Copy)]
+struct ValueSerializer<'bump> {
+    bump: &'bump Bump,
+}
+
+impl<'bump> ValueSerializer<'bump> {
+    fn string(self, str: &str) -> JsonValue<'bump> {
+        JsonValue::String(self.bump.alloc(String::from_str_in(str, self.bump)))
+    }
+
+    /// Compared as `i128`s, as casting `2^63` back to an `i64` would saturate
+    /// to `i64::MAX`
+    fn integer(self, v: i128) -> Result<JsonValue<'bump>, SerError> {
+        let num = v as f64;
+        if num as i128 != v {
+            return Err(SerError::InexactInteger(v));
+        }
+        Ok(JsonValue::Number(num))
+    }
+
+    /// Wraps `value` as `{ variant: value }`, the externally tagged enum layout
+    fn variant(self, variant: &str, value: JsonValue<'bump>) -> JsonValue<'bump> {
+        let mut obj = HashMap::with_capacity_in(1, BumpWrapper(self.bump));
+        obj.insert(&*self.bump.alloc_str(variant), value);
+        JsonValue::Object(self.bump.alloc(obj))
+    }
+}
+
+impl<'bump> Serializer for ValueSerializer<'bump> {
+    type Ok = JsonValue<'bump>;
+    type Error = SerError;
+
+    type SerializeSeq = ArraySerializer<'bump>;
+    type SerializeTuple = ArraySerializer<'bump>;
+    type SerializeTupleStruct = ArraySerializer<'bump>;
+    type SerializeTupleVariant = ArraySerializer<'bump>;
+    type SerializeMap = ObjectSerializer<'bump>;
+    type SerializeStruct = ObjectSerializer<'bump>;
+    type SerializeStructVariant = ObjectSerializer<'bump>;
+
+    fn serialize_bool(self, v: bool) -> Result<JsonValue<'bump>, SerError> {
+        Ok(JsonValue::Boolean(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<JsonValue<'bump>, SerError> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<JsonValue<'bump>, SerError> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<JsonValue<'bump>, SerError> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<JsonValue<'bump>, SerError> {
+        self.integer(v as i128)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<JsonValue<'bump>, SerError> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<JsonValue<'bump>, SerError> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<JsonValue<'bump>, SerError> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<JsonValue<'bump>, SerError> {
+        self.integer(v as i128)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<JsonValue<'bump>, SerError> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<JsonValue<'bump>, SerError> {
+        match v.is_finite() {
+            true => Ok(JsonValue::Number(v)),
+            false => Ok(JsonValue::Null),
+        }
+    }
+
+    fn serialize_char(self, v: char) -> Result<JsonValue<'bump>, SerError> {
+        Ok(self.string(v.encode_utf8(&mut [0; 4])))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<JsonValue<'bump>, SerError> {
+        Ok(self.string(v))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<JsonValue<'bump>, SerError> {
+        let mut arr = Vec::with_capacity_in(v.len(), self.bump);
+        arr.extend(v.iter().map(|byte| JsonValue::Number(*byte as f64)));
+        Ok(JsonValue::Array(self.bump.alloc(arr)))
+    }
+
+    fn serialize_none(self) -> Result<JsonValue<'bump>, SerError> {
+        Ok(JsonValue::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(
+        self,
+        value: &T,
+    ) -> Result<JsonValue<'bump>, SerError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<JsonValue<'bump>, SerError> {
+        Ok(JsonValue::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<JsonValue<'bump>, SerError> {
+        Ok(JsonValue::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<JsonValue<'bump>, SerError> {
+        Ok(self.string(variant))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<JsonValue<'bump>, SerError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<JsonValue<'bump>, SerError> {
+        let value = value.serialize(self)?;
+        Ok(self.variant(variant, value))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<ArraySerializer<'bump>, SerError> {
+        Ok(ArraySerializer {
+            ser: self,
+            arr: Vec::with_capacity_in(len.unwrap_or(0), self.bump),
+            variant: None,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<ArraySerializer<'bump>, SerError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<ArraySerializer<'bump>, SerError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<ArraySerializer<'bump>, SerError> {
+        let mut ser = self.serialize_seq(Some(len))?;
+        ser.variant = Some(variant);
+        Ok(ser)
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<ObjectSerializer<'bump>, SerError> {
+        Ok(ObjectSerializer {
+            ser: self,
+            obj: HashMap::with_capacity_in(len.unwrap_or(0), BumpWrapper(self.bump)),
+            key: None,
+            variant: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<ObjectSerializer<'bump>, SerError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<ObjectSerializer<'bump>, SerError> {
+        let mut ser = self.serialize_map(Some(len))?;
+        ser.variant = Some(variant);
+        Ok(ser)
+    }
+}
+
+struct ArraySerializer<'bump> {
+    ser: ValueSerializer<'bump>,
+    arr: Vec<'bump, JsonValue<'bump>>,
+    variant: Option<&'static str>,
+}
+
+impl<'bump> ArraySerializer<'bump> {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerError> {
+        self.arr.push(value.serialize(self.ser)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<JsonValue<'bump>, SerError> {
+        let arr = JsonValue::Array(self.ser.bump.alloc(self.arr));
+        match self.variant {
+            Some(variant) => Ok(self.ser.variant(variant, arr)),
+            None => Ok(arr),
+        }
+    }
+}
+
+impl<'bump> SerializeSeq for ArraySerializer<'bump> {
+    type Ok = JsonValue<'bump>;
+    type Error = SerError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<JsonValue<'bump>, SerError> {
+        self.finish()
+    }
+}
+
+impl<'bump> ser::SerializeTuple for ArraySerializer<'bump> {
+    type Ok = JsonValue<'bump>;
+    type Error = SerError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<JsonValue<'bump>, SerError> {
+        self.finish()
+    }
+}
+
+impl<'bump> ser::SerializeTupleStruct for ArraySerializer<'bump> {
+    type Ok = JsonValue<'bump>;
+    type Error = SerError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<JsonValue<'bump>, SerError> {
+        self.finish()
+    }
+}
+
+impl<'bump> ser::SerializeTupleVariant for ArraySerializer<'bump> {
+    type Ok = JsonValue<'bump>;
+    type Error = SerError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<JsonValue<'bump>, SerError> {
+        self.finish()
+    }
+}
+
+struct ObjectSerializer<'bump> {
+    ser: ValueSerializer<'bump>,
+    obj: JsonObject<'bump>,
+    key: Option<String<'bump>>,
+    variant: Option<&'static str>,
+}
+
+impl<'bump> ObjectSerializer<'bump> {
+    fn insert<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<(), SerError> {
+        let value = value.serialize(self.ser)?;
+        self.obj.insert(self.ser.bump.alloc_str(key), value);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<JsonValue<'bump>, SerError> {
+        let obj = JsonValue::Object(self.ser.bump.alloc(self.obj));
+        match self.variant {
+            Some(variant) => Ok(self.ser.variant(variant, obj)),
+            None => Ok(obj),
+        }
+    }
+}
+
+impl<'bump> SerializeMap for ObjectSerializer<'bump> {
+    type Ok = JsonValue<'bump>;
+    type Error = SerError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), SerError> {
+        self.key = Some(key.serialize(KeySerializer {
+            bump: self.ser.bump,
+        })?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerError> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| <SerError as ser::Error>::custom("value serialized before key"))?;
+        let value = value.serialize(self.ser)?;
+        self.obj.insert(key.into_bump_str(), value);
+        Ok(())
+    }
+
+    fn end(self) -> Result<JsonValue<'bump>, SerError> {
+        self.finish()
+    }
+}
+
+impl<'bump> ser::SerializeStruct for ObjectSerializer<'bump> {
+    type Ok = JsonValue<'bump>;
+    type Error = SerError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerError> {
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<JsonValue<'bump>, SerError> {
+        self.finish()
+    }
+}
+
+impl<'bump> ser::SerializeStructVariant for ObjectSerializer<'bump> {
+    type Ok = JsonValue<'bump>;
+    type Error = SerError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerError> {
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<JsonValue<'bump>, SerError> {
+        self.finish()
+    }
+}
+
+/// Turns map keys into object keys, which must be strings in JSON.
+struct KeySerializer<'bump> {
+    bump: &'bump Bump,
+}
+
+impl<'bump> KeySerializer<'bump> {
+    fn display<T: Display>(self, v: T) -> Result<String<'bump>, SerError> {
+        let mut key = String::new_in(self.bump);
+        std::fmt::Write::write_fmt(&mut key, format_args!("{v}"))
+            .map_err(<SerError as ser::Error>::custom)?;
+        Ok(key)
+    }
+
+    fn invalid(found: &'static str) -> Result<String<'bump>, SerError> {
+        Err(SerError::InvalidKey { found })
+    }
+}
+
+impl<'bump> Serializer for KeySerializer<'bump> {
+    type Ok = String<'bump>;
+    type Error = SerError;
+
+    type SerializeSeq = Impossible<String<'bump>, SerError>;
+    type SerializeTuple = Impossible<String<'bump>, SerError>;
+    type SerializeTupleStruct = Impossible<String<'bump>, SerError>;
+    type SerializeTupleVariant = Impossible<String<'bump>, SerError>;
+    type SerializeMap = Impossible<String<'bump>, SerError>;
+    type SerializeStruct = Impossible<String<'bump>, SerError>;
+    type SerializeStructVariant = Impossible<String<'bump>, SerError>;
+
+    fn serialize_bool(self, _v: bool) -> Result<String<'bump>, SerError> {
+        Self::invalid("a boolean")
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<String<'bump>, SerError> {
+        self.display(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<String<'bump>, SerError> {
+        self.display(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<String<'bump>, SerError> {
+        self.display(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<String<'bump>, SerError> {
+        self.display(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<String<'bump>, SerError> {
+        self.display(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<String<'bump>, SerError> {
+        self.display(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<String<'bump>, SerError> {
+        self.display(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<String<'bump>, SerError> {
+        self.display(v)
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<String<'bump>, SerError> {
+        Self::invalid("a float")
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<String<'bump>, SerError> {
+        Self::invalid("a float")
+    }
+
+    fn serialize_char(self, v: char) -> Result<String<'bump>, SerError> {
+        self.display(v)
+    }
+
+    fn serialize_str(self, v: &str) -> Result<String<'bump>, SerError> {
+        Ok(String::from_str_in(v, self.bump))
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<String<'bump>, SerError> {
+        Self::invalid("bytes")
+    }
+
+    fn serialize_none(self) -> Result<String<'bump>, SerError> {
+        Self::invalid("none")
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<String<'bump>, SerError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<String<'bump>, SerError> {
+        Self::invalid("a unit")
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<String<'bump>, SerError> {
+        Self::invalid("a unit struct")
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<String<'bump>, SerError> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<String<'bump>, SerError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<String<'bump>, SerError> {
+        Self::invalid("an enum")
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, SerError> {
+        Err(SerError::InvalidKey {
+            found: "a sequence",
+        })
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, SerError> {
+        Err(SerError::InvalidKey { found: "a tuple" })
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, SerError> {
+        Err(SerError::InvalidKey { found: "a tuple" })
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, SerError> {
+        Err(SerError::InvalidKey { found: "an enum" })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, SerError> {
+        Err(SerError::InvalidKey { found: "a map" })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, SerError> {
+        Err(SerError::InvalidKey { found: "a struct" })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, SerError> {
+        Err(SerError::InvalidKey { found: "an enum" })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::Serialize;
+
+    use super::*;
+    use crate::parse;
+
+    #[derive(Serialize)]
+    struct Response<'a> {
+        id: u32,
+        name: &'a str,
+        tags: [&'a str; 2],
+        scores: BTreeMap<u8, f64>,
+        status: Status,
+        missing: Option<bool>,
+    }
+
+    #[derive(Serialize)]
+    enum Status {
+        Moved { to: &'static str },
+    }
+
+    #[test]
+    fn round_trip() {
+        let json = r#"{"a":[1,-2,3.5,"x\"y",true,null,{}],"b":{"c":[]},"d":1e300}"#;
+        let bump = Bump::new();
+        let val = parse(json.as_bytes(), &bump).unwrap();
+        let written = serde_json::to_value(val).unwrap();
+        assert_eq!(
+            written,
+            serde_json::from_str::<serde_json::Value>(json).unwrap()
+        );
+
+        let doc = JsonDocument::parse_create(json.as_bytes()).unwrap();
+        assert_eq!(serde_json::to_value(&doc).unwrap(), written);
+    }
+
+    #[test]
+    fn to_value() {
+        let bump = Bump::new();
+        let response = Response {
+            id: 4,
+            name: "Steve",
+            tags: ["a", "b"],
+            scores: BTreeMap::from([(1, 0.5), (2, f64::NAN)]),
+            status: Status::Moved { to: "/new" },
+            missing: None,
+        };
+        let mut val = to_value_in(&response, &bump).unwrap();
+        let expected = br#"{"id":4,"name":"Steve","tags":["a","b"],"scores":{"1":0.5,"2":null},
+            "status":{"Moved":{"to":"/new"}},"missing":null}"#;
+        assert_eq!(val, parse(expected, &bump).unwrap());
+
+        // Still a regular tree that can be edited
+        if let JsonValue::Object(obj) = &mut val {
+            obj.remove("missing");
+        }
+        let written = serde_json::to_value(&val).unwrap();
+        assert_eq!(written.as_object().unwrap().len(), 5);
+        assert_eq!(written["scores"]["1"], 0.5);
+    }
+
+    #[test]
+    fn integers() {
+        let bump = Bump::new();
+        for exact in [1 << 53, 1 << 60, u64::MAX - (1 << 11) + 1] {
+            let val = to_value_in(&exact, &bump).unwrap();
+            assert_eq!(val, JsonValue::Number(exact as f64));
+        }
+        to_value_in(&i64::MIN, &bump).unwrap();
+        for inexact in [(1 << 53) + 1, u64::MAX] {
+            assert!(matches!(
+                to_value_in(&inexact, &bump),
+                Err(SerError::InexactInteger(v)) if v == inexact as i128
+            ));
+        }
+        assert!(matches!(
+            to_value_in(&i64::MAX, &bump),
+            Err(SerError::InexactInteger(_))
+        ));
+    }
+}
@@ -0,0 +1,448 @@
+//! The byte comparisons behind the string scanner and the structural indexer,
+//! with one kernel per instruction set.
+//!
+//! The widest kernel the build and CPU support is used: AVX-512BW with the
+//! `avx512` feature, then AVX2, then a 16-byte one, which is `std::simd`
+//! with the `portable_simd` feature (which needs nightly), and otherwise SSE2
+//! on x86 or NEON on aarch64. Anything else uses plain loops.
+//!
+//! The kernel is picked on first use, unless the `RUST_JSON_PARSE_SIMD`
+//! environment variable names one: `scalar`, `portable`, `sse2`, `avx2`,
+//! `avx512` or `neon`, in any case. A name that isn't one of those, or a
+//! kernel this build or CPU can't run, is ignored, and
+//! `simd_kernel_env_error` says why. The kernel can be switched later with
+//! `set_simd_kernel`.
+
+#[cfg(test)]
+use std::cell::Cell;
+use std::env;
+use std::str::FromStr;
+use std::string::String as StdString;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+mod neon;
+#[cfg(feature = "portable_simd")]
+mod portable;
+mod scalar;
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature = "sse2"
+))]
+mod x86;
+
+/// A set of SIMD instructions the scanners can be run with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SimdKernel {
+    /// Plain loops, always available
+    Scalar,
+    /// `std::simd`, with the `portable_simd` feature
+    Portable,
+    Sse2,
+    Avx2,
+    /// AVX-512BW, with the `avx512` feature
+    Avx512,
+    Neon,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SimdKernelError {
+    #[error("Unknown SIMD kernel {0:?}")]
+    Unknown(StdString),
+    #[error("SIMD kernel {0:?} is not supported by this build or CPU")]
+    Unsupported(SimdKernel),
+}
+
+impl SimdKernel {
+    const ALL: [SimdKernel; 6] = [
+        SimdKernel::Scalar,
+        SimdKernel::Portable,
+        SimdKernel::Sse2,
+        SimdKernel::Avx2,
+        SimdKernel::Avx512,
+        SimdKernel::Neon,
+    ];
+
+    /// Whether this kernel was compiled in and the CPU can run it
+    pub fn is_supported(self) -> bool {
+        match self {
+            SimdKernel::Scalar => true,
+            SimdKernel::Portable => cfg!(feature = "portable_simd"),
+            SimdKernel::Sse2 => cfg!(all(
+                any(target_arch = "x86", target_arch = "x86_64"),
+                target_feature = "sse2"
+            )),
+            #[cfg(all(
+                any(target_arch = "x86", target_arch = "x86_64"),
+                target_feature = "sse2"
+            ))]
+            SimdKernel::Avx2 => is_x86_feature_detected!("avx2"),
+            #[cfg(not(all(
+                any(target_arch = "x86", target_arch = "x86_64"),
+                target_feature = "sse2"
+            )))]
+            SimdKernel::Avx2 => false,
+            #[cfg(all(
+                any(target_arch = "x86", target_arch = "x86_64"),
+                target_feature = "sse2",
+                feature = "avx512"
+            ))]
+            SimdKernel::Avx512 => is_x86_feature_detected!("avx512bw"),
+            #[cfg(not(all(
+                any(target_arch = "x86", target_arch = "x86_64"),
+                target_feature = "sse2",
+                feature = "avx512"
+            )))]
+            SimdKernel::Avx512 => false,
+            SimdKernel::Neon => cfg!(all(target_arch = "aarch64", target_feature = "neon")),
+        }
+    }
+
+    /// All the kernels this build can run on this CPU
+    pub fn supported() -> impl Iterator<Item = SimdKernel> {
+        Self::ALL.into_iter().filter(|kernel| kernel.is_supported())
+    }
+
+    /// Bytes `eq_any_mask` compares at once
+    pub(crate) fn width(self) -> usize {
+        match self {
+            SimdKernel::Avx2 => 32,
+            SimdKernel::Avx512 => 64,
+            _ => 16,
+        }
+    }
+
+    /// The 16-byte kernel of the same instruction set, for short runs where
+    /// a wider load is mostly wasted
+    pub(crate) fn narrow(self) -> SimdKernel {
+        match self {
+            SimdKernel::Avx2 | SimdKernel::Avx512 => SimdKernel::Sse2,
+            kernel => kernel,
+        }
+    }
+
+    /// The kernel `name` asks for, if this build and CPU can run it
+    fn from_env(name: &str) -> Result<SimdKernel, SimdKernelError> {
+        let kernel = name.parse::<SimdKernel>()?;
+        if !kernel.is_supported() {
+            return Err(SimdKernelError::Unsupported(kernel));
+        }
+        Ok(kernel)
+    }
+
+    fn detect() -> SimdKernel {
+        if let Ok(name) = env::var("RUST_JSON_PARSE_SIMD") {
+            if let Ok(kernel) = SimdKernel::from_env(&name) {
+                return kernel;
+            }
+        }
+        // Widest first, with `std::simd` ahead of the other 16-byte kernels
+        // when it has been asked for
+        [
+            SimdKernel::Avx512,
+            SimdKernel::Avx2,
+            SimdKernel::Portable,
+            SimdKernel::Sse2,
+            SimdKernel::Neon,
+        ]
+        .into_iter()
+        .find(|kernel| kernel.is_supported())
+        .unwrap_or(SimdKernel::Scalar)
+    }
+}
+
+impl FromStr for SimdKernel {
+    type Err = SimdKernelError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        SimdKernel::ALL
+            .into_iter()
+            .find(|kernel| format!("{kernel:?}").eq_ignore_ascii_case(name))
+            .ok_or_else(|| SimdKernelError::Unknown(name.to_string()))
+    }
+}
+
+const UNDETECTED: u8 = u8::MAX;
+
+static KERNEL: AtomicU8 = AtomicU8::new(UNDETECTED);
+
+#[cfg(test)]
+thread_local! {
+    /// Overrides `KERNEL` for one thread, so tests can compare kernels
+    /// without switching it under the tests running alongside them
+    static THREAD_KERNEL: Cell<Option<SimdKernel>> = Cell::new(None);
+}
+
+/// The kernel the scanners currently use
+#[inline]
+pub fn simd_kernel() -> SimdKernel {
+    #[cfg(test)]
+    if let Some(kernel) = THREAD_KERNEL.with(Cell::get) {
+        return kernel;
+    }
+    match KERNEL.load(Ordering::Relaxed) {
+        UNDETECTED => {
+            let kernel = SimdKernel::detect();
+            KERNEL.store(kernel as u8, Ordering::Relaxed);
+            kernel
+        }
+        kernel => SimdKernel::ALL[kernel as usize],
+    }
+}
+
+/// Why the current value of `RUST_JSON_PARSE_SIMD` is ignored, if it is set
+/// to something other than a kernel this build and CPU can run
+pub fn simd_kernel_env_error() -> Option<SimdKernelError> {
+    let name = env::var("RUST_JSON_PARSE_SIMD").ok()?;
+    SimdKernel::from_env(&name).err()
+}
+
+/// Switches every later parse, in any thread, to `kernel`
+pub fn set_simd_kernel(kernel: SimdKernel) -> Result<(), SimdKernelError> {
+    if !kernel.is_supported() {
+        return Err(SimdKernelError::Unsupported(kernel));
+    }
+    KERNEL.store(kernel as u8, Ordering::Relaxed);
+    Ok(())
+}
+
+/// A mask with bit `i` set if `chunk[i]` is one of `bytes`, or less than
+/// `below`
+///
+/// # Safety
+///
+/// `kernel` must be supported, and `chunk` must be `kernel.width()` long.
+#[inline]
+pub(crate) unsafe fn eq_any_mask<const N: usize>(
+    kernel: SimdKernel,
+    chunk: &[u8],
+    bytes: [u8; N],
+    below: u8,
+) -> u64 {
+    debug_assert_eq!(chunk.len(), kernel.width());
+    let ptr = chunk.as_ptr();
+    match kernel {
+        #[cfg(feature = "portable_simd")]
+        SimdKernel::Portable => portable::eq_any_mask16(&*(ptr as *const [u8; 16]), bytes, below),
+        #[cfg(all(
+            any(target_arch = "x86", target_arch = "x86_64"),
+            target_feature = "sse2",
+            feature = "avx512"
+        ))]
+        SimdKernel::Avx512 => x86::eq_any_mask64_avx512(&*(ptr as *const [u8; 64]), bytes, below),
+        #[cfg(all(
+            any(target_arch = "x86", target_arch = "x86_64"),
+            target_feature = "sse2"
+        ))]
+        SimdKernel::Avx2 => x86::eq_any_mask32_avx2(&*(ptr as *const [u8; 32]), bytes, below),
+        #[cfg(all(
+            any(target_arch = "x86", target_arch = "x86_64"),
+            target_feature = "sse2"
+        ))]
+        SimdKernel::Sse2 => x86::eq_any_mask16(&*(ptr as *const [u8; 16]), bytes, below),
+        #[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+        SimdKernel::Neon => neon::eq_any_mask16(&*(ptr as *const [u8; 16]), bytes, below),
+        _ => scalar::eq_any_mask16(&*(ptr as *const [u8; 16]), bytes, below),
+    }
+}
+
+/// For each of `bytes`, a mask with bit `i` set if `chunk[i]` is that byte
+#[inline]
+pub(crate) fn eq_masks64<const N: usize>(chunk: &[u8; 64], bytes: [u8; N]) -> [u64; N] {
+    // SAFETY: `simd_kernel` only returns supported kernels
+    unsafe { eq_masks64_with(simd_kernel(), chunk, bytes) }
+}
+
+/// # Safety
+///
+/// `kernel` must be supported.
+#[inline]
+unsafe fn eq_masks64_with<const N: usize>(
+    kernel: SimdKernel,
+    chunk: &[u8; 64],
+    bytes: [u8; N],
+) -> [u64; N] {
+    match kernel {
+        #[cfg(feature = "portable_simd")]
+        SimdKernel::Portable => portable::eq_masks64(chunk, bytes),
+        #[cfg(all(
+            any(target_arch = "x86", target_arch = "x86_64"),
+            target_feature = "sse2",
+            feature = "avx512"
+        ))]
+        SimdKernel::Avx512 => x86::eq_masks64_avx512(chunk, bytes),
+        #[cfg(all(
+            any(target_arch = "x86", target_arch = "x86_64"),
+            target_feature = "sse2"
+        ))]
+        SimdKernel::Avx2 => x86::eq_masks64_avx2(chunk, bytes),
+        #[cfg(all(
+            any(target_arch = "x86", target_arch = "x86_64"),
+            target_feature = "sse2"
+        ))]
+        SimdKernel::Sse2 => x86::eq_masks64(chunk, bytes),
+        #[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+        SimdKernel::Neon => neon::eq_masks64(chunk, bytes),
+        _ => scalar::eq_masks64(chunk, bytes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bumpalo::Bump;
+
+    use super::*;
+    use crate::{parse, parse_with_options, Backend, ParseOptions, Tape};
+
+    /// Runs `f` with `kernel` on this thread only
+    fn with_kernel<T>(kernel: SimdKernel, f: impl FnOnce() -> T) -> T {
+        assert!(kernel.is_supported());
+        THREAD_KERNEL.with(|cell| cell.set(Some(kernel)));
+        let ret = f();
+        THREAD_KERNEL.with(|cell| cell.set(None));
+        ret
+    }
+
+    /// Bytes that exercise every lane, including ones with the top bit set
+    fn chunks<const N: usize>() -> impl Iterator<Item = [u8; N]> {
+        let mut state: u32 = 1;
+        let mut random = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        };
+        let special = b"\"\\{}[]:, \t\n\r\x80\xff";
+        (0..2000).map(move |i| {
+            std::array::from_fn(|_| match i % 3 {
+                0 => random(),
+                1 => special[random() as usize % special.len()],
+                _ => b'a' + random() % 4,
+            })
+        })
+    }
+
+    #[test]
+    fn detect() {
+        if env::var_os("RUST_JSON_PARSE_SIMD").is_some() {
+            return;
+        }
+        // Even with `portable_simd`
+        if SimdKernel::Avx512.is_supported() {
+            assert_eq!(SimdKernel::detect(), SimdKernel::Avx512);
+        } else if SimdKernel::Avx2.is_supported() {
+            assert_eq!(SimdKernel::detect(), SimdKernel::Avx2);
+        }
+        assert_eq!(simd_kernel_env_error().map(|e| e.to_string()), None);
+        assert_eq!(SimdKernel::from_env("scalar").unwrap(), SimdKernel::Scalar);
+        assert!(matches!(
+            SimdKernel::from_env("avx3"),
+            Err(SimdKernelError::Unknown(_))
+        ));
+        assert!("AVX2".parse::<SimdKernel>().is_ok());
+        assert!(matches!(
+            "avx3".parse::<SimdKernel>(),
+            Err(SimdKernelError::Unknown(_))
+        ));
+        let unsupported = SimdKernel::ALL.into_iter().find(|k| !k.is_supported());
+        if let Some(kernel) = unsupported {
+            let name = format!("{kernel:?}");
+            assert!(matches!(
+                SimdKernel::from_env(&name),
+                Err(SimdKernelError::Unsupported(_))
+            ));
+            assert!(matches!(
+                set_simd_kernel(kernel),
+                Err(SimdKernelError::Unsupported(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn kernels_agree() {
+        let bytes = *b"\"\\{}[]:, \t\n\r\x80\xff";
+        let kernels: Vec<SimdKernel> = SimdKernel::supported().collect();
+        assert!(kernels.contains(&simd_kernel()));
+        for chunk in chunks::<64>() {
+            let expected = scalar::eq_masks64(&chunk, bytes);
+            let expected_any = |n: usize| expected[..n].iter().fold(0, |any, mask| any | mask);
+            let quote_mask = expected_any(2);
+            let any_mask = expected_any(bytes.len());
+            let control_mask = (0..64).fold(0, |mask, i| mask | ((chunk[i] < 0x20) as u64) << i);
+            for kernel in &kernels {
+                let width = kernel.width();
+                let prefix = u64::MAX >> (64 - width);
+                let chunk_start = &chunk[..width];
+                unsafe {
+                    assert_eq!(
+                        eq_masks64_with(*kernel, &chunk, bytes),
+                        expected,
+                        "{kernel:?} {chunk:?}"
+                    );
+                    assert_eq!(
+                        eq_any_mask(*kernel, chunk_start, bytes, 0),
+                        any_mask & prefix,
+                        "{kernel:?} {chunk:?}"
+                    );
+                    assert_eq!(
+                        eq_any_mask(*kernel, chunk_start, [b'"', b'\\'], 0x20),
+                        (quote_mask | control_mask) & prefix,
+                        "{kernel:?} {chunk:?}"
+                    );
+                    assert_eq!(
+                        eq_any_mask(*kernel, chunk_start, [], 0xff),
+                        !chunk
+                            .iter()
+                            .enumerate()
+                            .fold(0, |mask, (i, byte)| mask | ((*byte == 0xff) as u64) << i)
+                            & prefix,
+                        "{kernel:?} {chunk:?}"
+                    );
+                }
+            }
+        }
+        assert_eq!("avx2".parse::<SimdKernel>().unwrap(), SimdKernel::Avx2);
+        assert!("mmx".parse::<SimdKernel>().is_err());
+    }
+
+    #[test]
+    fn parsers_agree() {
+        let mut pretty = std::string::String::new();
+        for i in 0..200 {
+            pretty.push_str(&format!(
+                "{{\n  \"key {i}\" : [ \"a\\\"\\\\{}\", {i}.5 , true, null, {{ }} ],\t\"\\u00e9\" : \"{}\" }},\n",
+                "x".repeat(i % 40),
+                "é".repeat(i % 7)
+            ));
+        }
+        let pretty = format!("[{pretty} 0]");
+        let inputs: [&[u8]; 2] = [
+            include_bytes!("../../../tests/food.json"),
+            pretty.as_bytes(),
+        ];
+        let structural = ParseOptions {
+            backend: Backend::Structural,
+            ..Default::default()
+        };
+        for json in inputs {
+            let bump = Bump::new();
+            let expected = with_kernel(SimdKernel::Scalar, || parse(json, &bump).unwrap());
+            for kernel in SimdKernel::supported() {
+                with_kernel(kernel, || {
+                    assert_eq!(simd_kernel(), kernel);
+                    assert_eq!(parse(json, &bump).unwrap(), expected, "{kernel:?}");
+                    assert_eq!(
+                        parse_with_options(json, &bump, &structural).unwrap(),
+                        expected,
+                        "{kernel:?}"
+                    );
+                    assert_eq!(
+                        Tape::parse(json).unwrap().to_value(&bump),
+                        expected,
+                        "{kernel:?}"
+                    );
+                });
+            }
+        }
+    }
+}
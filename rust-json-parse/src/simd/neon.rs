@@ -0,0 +1,39 @@
+//! NEON, which every aarch64 CPU has
+
+use std::arch::aarch64::*;
+
+/// Bit `i % 8` for lane `i`, so pairwise adds can pack lanes into bits
+const LANE_BITS: [u8; 16] = [1, 2, 4, 8, 16, 32, 64, 128, 1, 2, 4, 8, 16, 32, 64, 128];
+
+#[inline]
+pub fn eq_any_mask16<const N: usize>(chunk: &[u8; 16], bytes: [u8; N], below: u8) -> u64 {
+    // SAFETY: only compiled when NEON is enabled for the target
+    unsafe {
+        let vector = vld1q_u8(chunk.as_ptr());
+        let mut any = vcltq_u8(vector, vdupq_n_u8(below));
+        for byte in bytes {
+            any = vorrq_u8(any, vceqq_u8(vector, vdupq_n_u8(byte)));
+        }
+        let bits = vandq_u8(any, vld1q_u8(LANE_BITS.as_ptr()));
+        let sum = vpaddq_u8(bits, bits);
+        let sum = vpaddq_u8(sum, sum);
+        let sum = vpaddq_u8(sum, sum);
+        vgetq_lane_u16(vreinterpretq_u16_u8(sum), 0) as u64
+    }
+}
+
+#[inline]
+pub fn eq_masks64<const N: usize>(chunk: &[u8; 64], bytes: [u8; N]) -> [u64; N] {
+    // SAFETY: only compiled when NEON is enabled for the target
+    unsafe {
+        let vectors = [0, 16, 32, 48].map(|i| vld1q_u8(chunk.as_ptr().add(i)));
+        let lane_bits = vld1q_u8(LANE_BITS.as_ptr());
+        bytes.map(|byte| {
+            let splat = vdupq_n_u8(byte);
+            let [a, b, c, d] = vectors.map(|v| vandq_u8(vceqq_u8(v, splat), lane_bits));
+            let sum = vpaddq_u8(vpaddq_u8(a, b), vpaddq_u8(c, d));
+            let sum = vpaddq_u8(sum, sum);
+            vgetq_lane_u64(vreinterpretq_u64_u8(sum), 0)
+        })
+    }
+}
@@ -0,0 +1,18 @@
+//! `std::simd`, only available on nightly
+
+use std::simd::{u8x16, u8x64, SimdPartialEq, SimdPartialOrd, ToBitMask};
+
+#[inline]
+pub fn eq_any_mask16<const N: usize>(chunk: &[u8; 16], bytes: [u8; N], below: u8) -> u64 {
+    let vector = u8x16::from_array(*chunk);
+    let below = vector.simd_lt(u8x16::splat(below)).to_bitmask() as u64;
+    bytes.into_iter().fold(below, |mask, byte| {
+        mask | vector.simd_eq(u8x16::splat(byte)).to_bitmask() as u64
+    })
+}
+
+#[inline]
+pub fn eq_masks64<const N: usize>(chunk: &[u8; 64], bytes: [u8; N]) -> [u64; N] {
+    let vector = u8x64::from_array(*chunk);
+    bytes.map(|byte| vector.simd_eq(u8x64::splat(byte)).to_bitmask())
+}
@@ -0,0 +1,20 @@
+//! Plain loops, for targets without a SIMD kernel
+
+#[inline]
+pub fn eq_any_mask16<const N: usize>(chunk: &[u8; 16], bytes: [u8; N], below: u8) -> u64 {
+    let mut mask = 0;
+    for (i, byte) in chunk.iter().enumerate() {
+        mask |= ((bytes.contains(byte) || *byte < below) as u64) << i;
+    }
+    mask
+}
+
+pub fn eq_masks64<const N: usize>(chunk: &[u8; 64], bytes: [u8; N]) -> [u64; N] {
+    let mut masks = [0; N];
+    for (i, byte) in chunk.iter().enumerate() {
+        for (mask, wanted) in masks.iter_mut().zip(bytes) {
+            *mask |= ((*byte == wanted) as u64) << i;
+        }
+    }
+    masks
+}
@@ -0,0 +1,118 @@
+//! SSE2, which every x86_64 CPU has, and AVX2 and AVX-512, which have to be
+//! detected
+
+#[cfg(target_arch = "x86")]
+use std::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+#[inline]
+pub fn eq_any_mask16<const N: usize>(chunk: &[u8; 16], bytes: [u8; N], below: u8) -> u64 {
+    // SAFETY: only compiled when SSE2 is enabled for the target
+    unsafe {
+        let vector = _mm_loadu_si128(chunk.as_ptr() as *const __m128i);
+        // There's no unsigned compare, but `x < below` is `min(x, below - 1) == x`
+        let mut any = match below {
+            0 => _mm_setzero_si128(),
+            _ => _mm_cmpeq_epi8(
+                _mm_min_epu8(vector, _mm_set1_epi8((below - 1) as i8)),
+                vector,
+            ),
+        };
+        for byte in bytes {
+            any = _mm_or_si128(any, _mm_cmpeq_epi8(vector, _mm_set1_epi8(byte as i8)));
+        }
+        _mm_movemask_epi8(any) as u16 as u64
+    }
+}
+
+#[inline]
+pub fn eq_masks64<const N: usize>(chunk: &[u8; 64], bytes: [u8; N]) -> [u64; N] {
+    // SAFETY: only compiled when SSE2 is enabled for the target
+    unsafe {
+        let ptr = chunk.as_ptr() as *const __m128i;
+        let vectors = [0, 1, 2, 3].map(|i| _mm_loadu_si128(ptr.add(i)));
+        bytes.map(|byte| {
+            let splat = _mm_set1_epi8(byte as i8);
+            let mut mask = 0;
+            for (i, vector) in vectors.iter().enumerate() {
+                let bits = _mm_movemask_epi8(_mm_cmpeq_epi8(*vector, splat)) as u16 as u64;
+                mask |= bits << (i * 16);
+            }
+            mask
+        })
+    }
+}
+
+/// # Safety
+///
+/// The CPU must support AVX2.
+#[target_feature(enable = "avx2")]
+pub unsafe fn eq_any_mask32_avx2<const N: usize>(
+    chunk: &[u8; 32],
+    bytes: [u8; N],
+    below: u8,
+) -> u64 {
+    let vector = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+    let mut any = match below {
+        0 => _mm256_setzero_si256(),
+        _ => _mm256_cmpeq_epi8(
+            _mm256_min_epu8(vector, _mm256_set1_epi8((below - 1) as i8)),
+            vector,
+        ),
+    };
+    for byte in bytes {
+        any = _mm256_or_si256(any, _mm256_cmpeq_epi8(vector, _mm256_set1_epi8(byte as i8)));
+    }
+    _mm256_movemask_epi8(any) as u32 as u64
+}
+
+/// # Safety
+///
+/// The CPU must support AVX2.
+#[target_feature(enable = "avx2")]
+pub unsafe fn eq_masks64_avx2<const N: usize>(chunk: &[u8; 64], bytes: [u8; N]) -> [u64; N] {
+    let ptr = chunk.as_ptr() as *const __m256i;
+    let low = _mm256_loadu_si256(ptr);
+    let high = _mm256_loadu_si256(ptr.add(1));
+    let mut masks = [0; N];
+    for (mask, byte) in masks.iter_mut().zip(bytes) {
+        let splat = _mm256_set1_epi8(byte as i8);
+        let low = _mm256_movemask_epi8(_mm256_cmpeq_epi8(low, splat)) as u32 as u64;
+        let high = _mm256_movemask_epi8(_mm256_cmpeq_epi8(high, splat)) as u32 as u64;
+        *mask = low | high << 32;
+    }
+    masks
+}
+
+/// # Safety
+///
+/// The CPU must support AVX-512BW.
+#[cfg(feature = "avx512")]
+#[target_feature(enable = "avx512bw")]
+pub unsafe fn eq_any_mask64_avx512<const N: usize>(
+    chunk: &[u8; 64],
+    bytes: [u8; N],
+    below: u8,
+) -> u64 {
+    let vector = _mm512_loadu_si512(chunk.as_ptr() as *const _);
+    let mut mask = _mm512_cmplt_epu8_mask(vector, _mm512_set1_epi8(below as i8));
+    for byte in bytes {
+        mask |= _mm512_cmpeq_epi8_mask(vector, _mm512_set1_epi8(byte as i8));
+    }
+    mask
+}
+
+/// # Safety
+///
+/// The CPU must support AVX-512BW.
+#[cfg(feature = "avx512")]
+#[target_feature(enable = "avx512bw")]
+pub unsafe fn eq_masks64_avx512<const N: usize>(chunk: &[u8; 64], bytes: [u8; N]) -> [u64; N] {
+    let vector = _mm512_loadu_si512(chunk.as_ptr() as *const _);
+    let mut masks = [0; N];
+    for (mask, byte) in masks.iter_mut().zip(bytes) {
+        *mask = _mm512_cmpeq_epi8_mask(vector, _mm512_set1_epi8(byte as i8));
+    }
+    masks
+}
@@ -1,4 +1,4 @@
-use std::simd::{u8x16, SimdPartialEq};
+use crate::simd;
 
 #[derive(Debug)]
 pub struct SliceIter<'a, T: Copy> {
@@ -48,22 +48,30 @@ pub trait CopyIter<'a>: Iterator {
         }
         self.take_many_ref(len).unwrap_or(&[])
     }
-
-    fn take_while_chunked<
-        const N: usize,
-        F1: Fn([Self::Item; N]) -> bool,
-        F2: Fn(Self::Item) -> bool,
-    >(
-        &mut self,
-        pred1: F1,
-        pred2: F2,
-    ) -> &'a [Self::Item];
 }
 
 impl<'a, T: Copy> SliceIter<'a, T> {
     pub fn new(slice: &'a [T]) -> Self {
         Self { slice, index: 0 }
     }
+
+    /// The offset of the next item in the underlying slice
+    #[inline]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The whole underlying slice, including what has already been consumed
+    #[inline]
+    pub fn as_slice(&self) -> &'a [T] {
+        self.slice
+    }
+
+    /// Moves back (or forward) to an offset previously returned by `index`
+    #[inline]
+    pub fn set_index(&mut self, index: usize) {
+        self.index = index.min(self.slice.len());
+    }
 }
 
 impl<'a, T: Copy> CopyIter<'a> for SliceIter<'a, T> {
@@ -108,37 +116,6 @@ impl<'a, T: Copy> CopyIter<'a> for SliceIter<'a, T> {
             self.index = self.slice.len();
         }
     }
-
-    #[inline]
-    fn take_while_chunked<
-        const N: usize,
-        F1: Fn([Self::Item; N]) -> bool,
-        F2: Fn(Self::Item) -> bool,
-    >(
-        &mut self,
-        pred1: F1,
-        pred2: F2,
-    ) -> &'a [Self::Item] {
-        let op_slice = &self.slice[self.index..];
-        let mut len = 0;
-        while let Some(chunk) = self.peek_many::<N>() {
-            if pred1(chunk) {
-                len += N;
-                self.index += N;
-            } else {
-                break;
-            }
-        }
-        while let Some(itm) = self.peek_copy() {
-            if pred2(itm) {
-                len += 1;
-                self.index += 1;
-            } else {
-                break;
-            }
-        }
-        return &op_slice[0..len];
-    }
 }
 
 impl<'a, T: Copy> From<&'a [T]> for SliceIter<'a, T> {
@@ -165,32 +142,76 @@ impl<'a, T: Copy> Iterator for SliceIter<'a, T> {
 }
 
 impl<'a> SliceIter<'a, u8> {
+    /// Moves past any JSON whitespace.
+    ///
+    /// Nothing or a single space, as after a `:` or `,`, is checked for first,
+    /// longer runs like indentation are skipped a SIMD block at a time.
     #[inline]
-    pub fn take_while_ne_simd<const N: usize, P: Fn(u8) -> bool>(
+    pub fn skip_whitespace(&mut self) {
+        match self.peek_copy() {
+            Some(b' ' | b'\n' | b'\r' | b'\t') => {}
+            _ => return,
+        }
+        if !matches!(self.peek_at_copy(1), Some(b' ' | b'\n' | b'\r' | b'\t')) {
+            self.index += 1;
+            return;
+        }
+        self.skip_whitespace_run();
+    }
+
+    /// The out-of-line part of `skip_whitespace`, for runs of two or more
+    #[inline(never)]
+    fn skip_whitespace_run(&mut self) {
+        // Indentation is rarely longer than 16 bytes
+        let kernel = simd::simd_kernel().narrow();
+        let width = kernel.width();
+        let block = u64::MAX >> (64 - width);
+        while let Some(chunk) = self.peek_many_ref(width) {
+            // SAFETY: `simd_kernel` only returns supported kernels, and the
+            // chunk is as wide as the kernel
+            let whitespace = unsafe { simd::eq_any_mask(kernel, chunk, *b" \n\r\t", 0) };
+            let other = !whitespace & block;
+            if other != 0 {
+                self.index += other.trailing_zeros() as usize;
+                return;
+            }
+            self.index += width;
+        }
+        while let Some(b' ' | b'\n' | b'\r' | b'\t') = self.peek_copy() {
+            self.index += 1;
+        }
+    }
+
+    /// Moves to the first byte that is one of `conditions` or less than
+    /// `below`, returning the bytes passed over.
+    ///
+    /// Compares a whole SIMD block at a time and jumps straight to the first
+    /// match in it with the block's bitmask.
+    #[inline]
+    pub fn take_while_ne_simd<const N: usize>(
         &mut self,
-        conditions: [u8x16; N],
-        pred: P,
-    ) -> &[u8] {
-        let op_slice = &self.slice[self.index..];
-        let mut len = 0;
-        'outer: while let Some(chnk) = self.peek_many() {
-            let vector = u8x16::from_array(chnk);
-            for condition in conditions {
-                if vector.simd_eq(condition).any() {
-                    break 'outer;
-                }
+        conditions: [u8; N],
+        below: u8,
+    ) -> &'a [u8] {
+        let start = self.index;
+        let kernel = simd::simd_kernel();
+        let width = kernel.width();
+        while let Some(chunk) = self.peek_many_ref(width) {
+            // SAFETY: `simd_kernel` only returns supported kernels, and the
+            // chunk is as wide as the kernel
+            let mask = unsafe { simd::eq_any_mask(kernel, chunk, conditions, below) };
+            if mask != 0 {
+                self.index += mask.trailing_zeros() as usize;
+                return &self.slice[start..self.index];
             }
-            len += 8;
-            self.index += 8;
+            self.index += width;
         }
         while let Some(byte) = self.peek_copy() {
-            if pred(byte) {
-                self.index += 1;
-                len += 1;
-            } else {
+            if conditions.contains(&byte) || byte < below {
                 break;
             }
+            self.index += 1;
         }
-        &op_slice[0..len]
+        &self.slice[start..self.index]
     }
 }
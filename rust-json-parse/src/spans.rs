@@ -0,0 +1,350 @@
+//! Where each value of a parsed document came from in its input.
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::string::String as StdString;
+use std::vec::Vec as StdVec;
+
+use bumpalo::Bump;
+
+use crate::parsers::json5::read_identifier;
+use crate::parsers::string::read_key;
+use crate::slice_iter::{CopyIter, SliceIter};
+use crate::{encoding, parse_error, parse_next, parse_with_options, skip_ws};
+use crate::{Dialect, JsonError, JsonResult, JsonValue, ParseContext, ParseOptions, ParseState};
+
+/// The byte ranges of every value and object key in a document, by JSON
+/// Pointer, e.g. `/servers/0/port`.
+///
+/// Ranges are into the input as given, byte-order mark included. Where an
+/// object has a key more than once, only the last one and the values inside
+/// it are recorded, matching the value the object keeps.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SpanMap {
+    /// Each value in the order it starts, so the values inside one follow it
+    spans: StdVec<Span>,
+    /// Where each pointer's latest value is in `spans`
+    index: HashMap<StdString, usize>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Span {
+    value: Range<usize>,
+    /// For object members, the span of the key
+    key: Option<Range<usize>>,
+    /// Just past the last value inside this one in `spans`
+    end: usize,
+    /// Overwritten by a later member with the same key
+    replaced: bool,
+}
+
+impl SpanMap {
+    fn get(&self, pointer: &str) -> Option<&Span> {
+        let span = &self.spans[*self.index.get(pointer)?];
+        (!span.replaced).then_some(span)
+    }
+
+    /// The span of the value at `pointer`, from its first byte to just past
+    /// its last
+    pub fn value(&self, pointer: &str) -> Option<Range<usize>> {
+        Some(self.get(pointer)?.value.clone())
+    }
+
+    /// The span of the key of the object member at `pointer`, quotes included
+    pub fn key(&self, pointer: &str) -> Option<Range<usize>> {
+        self.get(pointer)?.key.clone()
+    }
+
+    /// The span of a whole object member, from the start of its key to the
+    /// end of its value, like `"port": "80"`
+    pub fn member(&self, pointer: &str) -> Option<Range<usize>> {
+        Some(self.key(pointer)?.start..self.value(pointer)?.end)
+    }
+
+    /// Every value's pointer and span, in no particular order
+    pub fn values(&self) -> impl Iterator<Item = (&str, Range<usize>)> {
+        self.index.iter().filter_map(|(pointer, &i)| {
+            let span = &self.spans[i];
+            (!span.replaced).then(|| (pointer.as_str(), span.value.clone()))
+        })
+    }
+}
+
+/// Parses like `parse_with_options`, also recording the span of every value
+/// and key.
+///
+/// The spans are found in a second pass over the input once it has parsed,
+/// which reads every key and scalar again, so this is about twice as slow as
+/// parsing alone.
+pub fn parse_with_spans<'bump>(
+    json_buf: &[u8],
+    allocator: &'bump Bump,
+    options: &ParseOptions,
+) -> Result<(JsonValue<'bump>, SpanMap), JsonError> {
+    let value = parse_with_options(json_buf, allocator, options)?;
+    let ctx = ParseContext {
+        options: *options,
+        ..Default::default()
+    };
+    let mut json = SliceIter::new(json_buf);
+    if options.skip_bom && json_buf.starts_with(encoding::UTF8_BOM) {
+        json.ignore_many(encoding::UTF8_BOM.len());
+    }
+    let mut recorder = Recorder {
+        spans: SpanMap::default(),
+        pointer: StdString::new(),
+        key: None,
+        scratch: Bump::new(),
+        ctx,
+    };
+    skip_ws(&mut json, ctx).map_err(|e| parse_error(e, &json_buf[json.index()..]))?;
+    if json.peek_copy().is_some() {
+        recorder
+            .walk(&mut json)
+            .map_err(|e| parse_error(e, &json_buf[json.index()..]))?;
+    }
+    Ok((value, recorder.spans))
+}
+
+struct Recorder {
+    spans: SpanMap,
+    /// The pointer to the value being walked
+    pointer: StdString,
+    /// The span of the key of the value about to be walked
+    key: Option<Range<usize>>,
+    /// Scalars and keys are parsed here, and dropped straight away
+    scratch: Bump,
+    ctx: ParseContext,
+}
+
+/// An array or object being walked
+struct Open {
+    /// Its own span in `SpanMap::spans`
+    span: usize,
+    /// The length of the pointer to it
+    pointer: usize,
+    /// The index of the element being walked, for arrays
+    index: Option<usize>,
+}
+
+impl Recorder {
+    /// Walks a value that has already parsed, so only positions are tracked.
+    /// It doesn't recurse, so like `Backend::Structural` nesting depth is
+    /// only limited by memory
+    fn walk(&mut self, json: &mut SliceIter<'_, u8>) -> JsonResult<()> {
+        let mut open: StdVec<Open> = StdVec::new();
+        loop {
+            let span = self.start(json.index());
+            let index = match json.peek_copy() {
+                Some(b'[') => Some(0),
+                Some(b'{') => None,
+                _ => {
+                    self.scratch.reset();
+                    parse_next(json, &self.scratch, ParseState::Value, self.ctx)?;
+                    self.finish(span, json.index());
+                    if !self.next(json, &mut open)? {
+                        return Ok(());
+                    }
+                    continue;
+                }
+            };
+            json.ignore_next();
+            skip_ws(json, self.ctx)?;
+            if let Some(b']' | b'}') = json.peek_copy() {
+                json.ignore_next();
+                self.finish(span, json.index());
+                if !self.next(json, &mut open)? {
+                    return Ok(());
+                }
+                continue;
+            }
+            open.push(Open {
+                span,
+                pointer: self.pointer.len(),
+                index,
+            });
+            match index {
+                Some(_) => self.pointer.push_str("/0"),
+                None => self.key(json)?,
+            }
+        }
+    }
+
+    /// Moves past the value just walked to the start of the next one, closing
+    /// each container that ends on the way. Returns `false` at the end of the
+    /// whole document
+    fn next(&mut self, json: &mut SliceIter<'_, u8>, open: &mut StdVec<Open>) -> JsonResult<bool> {
+        while let Some(top) = open.last_mut() {
+            self.pointer.truncate(top.pointer);
+            skip_ws(json, self.ctx)?;
+            if json.next() == Some(b',') {
+                skip_ws(json, self.ctx)?;
+                // Unless it was a trailing comma
+                if !matches!(json.peek_copy(), Some(b']' | b'}')) {
+                    match &mut top.index {
+                        Some(index) => {
+                            *index += 1;
+                            self.pointer.push('/');
+                            self.pointer.push_str(&index.to_string());
+                        }
+                        None => self.key(json)?,
+                    }
+                    return Ok(true);
+                }
+                json.ignore_next();
+            }
+            let span = top.span;
+            open.pop();
+            self.finish(span, json.index());
+        }
+        Ok(false)
+    }
+
+    /// Reads an object key and the colon after it, moving the pointer to its
+    /// value
+    fn key(&mut self, json: &mut SliceIter<'_, u8>) -> JsonResult<()> {
+        let start = json.index();
+        self.scratch.reset();
+        let key = match json.peek_copy() {
+            Some(b'"' | b'\'') => read_key(json, &self.scratch, self.ctx)?,
+            _ if self.ctx.options.dialect == Dialect::Json5 => {
+                read_identifier(json, &self.scratch)?
+            }
+            _ => read_key(json, &self.scratch, self.ctx)?,
+        }
+        .replace('~', "~0")
+        .replace('/', "~1");
+        self.pointer.push('/');
+        self.pointer.push_str(&key);
+        self.key = Some(start..json.index());
+        skip_ws(json, self.ctx)?;
+        json.ignore_next();
+        skip_ws(json, self.ctx)
+    }
+
+    /// Records that the value at `pointer` starts at `offset`, and returns
+    /// where its span is
+    fn start(&mut self, offset: usize) -> usize {
+        let spans = &mut self.spans;
+        let i = spans.spans.len();
+        if let Some(old) = spans.index.insert(self.pointer.clone(), i) {
+            // A duplicate key: the earlier member's value, and every value
+            // inside it, is replaced. Each span is only replaced once, so
+            // this is linear in the input overall
+            let end = spans.spans[old].end;
+            for span in &mut spans.spans[old..end] {
+                span.replaced = true;
+            }
+        }
+        spans.spans.push(Span {
+            value: offset..offset,
+            key: self.key.take(),
+            end: i + 1,
+            replaced: false,
+        });
+        i
+    }
+
+    fn finish(&mut self, span: usize, offset: usize) {
+        let end = self.spans.spans.len();
+        let span = &mut self.spans.spans[span];
+        span.value.end = offset;
+        span.end = end;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Backend;
+
+    #[test]
+    fn spans() {
+        let json = r#"{
+  "name": "web",
+  "servers": [
+    {"host": "a.example", "port": "80"},
+    {"host": "b.example", "port": 8080, "a/b~": [true, null, -1.5e3]}
+  ],
+  "port": 1, "port": 2,
+  "empty": {}
+}"#;
+        let bump = Bump::new();
+        for backend in [Backend::Streaming, Backend::Structural] {
+            let options = ParseOptions {
+                backend,
+                ..Default::default()
+            };
+            let (value, spans) = parse_with_spans(json.as_bytes(), &bump, &options).unwrap();
+            assert_eq!(value, crate::parse(json.as_bytes(), &bump).unwrap());
+            let text = |span: Option<Range<usize>>| &json[span.unwrap()];
+            assert_eq!(text(spans.value("")), json);
+            assert_eq!(text(spans.value("/name")), r#""web""#);
+            assert_eq!(text(spans.key("/name")), r#""name""#);
+            assert_eq!(text(spans.member("/servers/0/port")), r#""port": "80""#);
+            assert_eq!(text(spans.value("/servers/1/a~1b~0/2")), "-1.5e3");
+            assert_eq!(
+                text(spans.value("/servers/1/a~1b~0")),
+                "[true, null, -1.5e3]"
+            );
+            assert_eq!(text(spans.member("/port")), r#""port": 2"#);
+            assert_eq!(text(spans.value("/empty")), "{}");
+            assert_eq!(spans.key("/servers/0"), None);
+            assert_eq!(spans.value("/missing"), None);
+            assert_eq!(spans.values().count(), 15);
+        }
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        let bump = Bump::new();
+        let json = r#"{"a": {"x": [1], "y": 2}, "ab": {"x": 3}, "a": 4}"#;
+        let (_, spans) = parse_with_spans(json.as_bytes(), &bump, &Default::default()).unwrap();
+        assert_eq!(&json[spans.member("/a").unwrap()], r#""a": 4"#);
+        for pointer in ["/a/x", "/a/x/0", "/a/y"] {
+            assert_eq!(spans.value(pointer), None, "{pointer}");
+            assert_eq!(spans.key(pointer), None, "{pointer}");
+        }
+        assert_eq!(&json[spans.value("/ab/x").unwrap()], "3");
+        assert_eq!(spans.values().count(), 4);
+        let json = r#"{"a": [{"b": 1, "b": {"c": 2}}], "a": {"d": [3]}, "a": {"d": 4}}"#;
+        let (_, spans) = parse_with_spans(json.as_bytes(), &bump, &Default::default()).unwrap();
+        assert_eq!(&json[spans.member("/a/d").unwrap()], r#""d": 4"#);
+        assert_eq!(spans.value("/a/d/0"), None);
+        assert_eq!(spans.value("/a/0/b/c"), None);
+        assert_eq!(spans.values().count(), 3);
+    }
+
+    #[test]
+    fn deep() {
+        let bump = Bump::new();
+        let json = "[".repeat(5_000) + "1" + &"]".repeat(5_000);
+        let options = ParseOptions {
+            backend: Backend::Structural,
+            ..Default::default()
+        };
+        let (_, spans) = parse_with_spans(json.as_bytes(), &bump, &options).unwrap();
+        assert_eq!(spans.value(&"/0".repeat(4_999)), Some(4_999..5_002));
+        assert_eq!(spans.value(&"/0".repeat(5_000)), Some(5_000..5_001));
+        assert_eq!(spans.values().count(), 5_001);
+    }
+
+    #[test]
+    fn options() {
+        let bump = Bump::new();
+        let json = "\u{FEFF}// c\n{a: 'x', /* b */ 'b': [1,],}";
+        let options = ParseOptions {
+            dialect: Dialect::Json5,
+            skip_bom: true,
+            ..Default::default()
+        };
+        let (_, spans) = parse_with_spans(json.as_bytes(), &bump, &options).unwrap();
+        assert_eq!(&json[spans.member("/a").unwrap()], "a: 'x'");
+        assert_eq!(&json[spans.key("/b").unwrap()], "'b'");
+        assert_eq!(&json[spans.value("/b/0").unwrap()], "1");
+        assert!(parse_with_spans(b"[1,", &bump, &options).is_err());
+        let (value, spans) = parse_with_spans(b" ", &bump, &ParseOptions::default()).unwrap();
+        assert_eq!(value, JsonValue::Null);
+        assert_eq!(spans, SpanMap::default());
+    }
+}
@@ -0,0 +1,424 @@
+//! A two-stage parser in the style of simdjson.
+//!
+//! Stage 1 classifies the input 64 bytes at a time into bitmasks, and from
+//! those finds the offset of every structural character and every value
+//! outside of strings. Stage 2 then builds the `JsonValue` by walking that
+//! index, reusing the string and number readers of the streaming parser.
+
+use std::vec::Vec as StdVec;
+
+use bumpalo::collections::Vec;
+use bumpalo::Bump;
+use hashbrown::{BumpWrapper, HashMap};
+
+use crate::parsers::number::read_number;
+use crate::parsers::string::{read_borrowed_str_with, read_key_str, read_string_with};
+use crate::simd::eq_masks64;
+use crate::slice_iter::{CopyIter, SliceIter};
+use crate::{is_whitespace, JsonObject, JsonResult, JsonValue, ParseContext, ParseError};
+
+const EVEN_BITS: u64 = 0x5555_5555_5555_5555;
+
+/// Bitmasks of one 64-byte block, bit `i` standing for byte `i`
+struct Block {
+    backslash: u64,
+    quote: u64,
+    op: u64,
+    whitespace: u64,
+}
+
+impl Block {
+    fn classify(chunk: &[u8; 64]) -> Self {
+        let [backslash, quote, open_brace, close_brace, open_bracket, close_bracket, colon, comma, space, tab, newline, cr] =
+            eq_masks64(chunk, *b"\\\"{}[]:, \t\n\r");
+        Self {
+            backslash,
+            quote,
+            op: open_brace | close_brace | open_bracket | close_bracket | colon | comma,
+            whitespace: space | tab | newline | cr,
+        }
+    }
+}
+
+/// State carried from one block to the next
+#[derive(Default)]
+struct Scanner {
+    /// The first byte of the next block is escaped by a trailing backslash
+    prev_escaped: u64,
+    /// All ones if the previous block ended inside a string
+    prev_in_string: u64,
+    /// The previous block ended in the middle of a number or literal
+    prev_scalar: u64,
+}
+
+impl Scanner {
+    /// Finds the bytes that are escaped by an odd run of backslashes before them
+    fn escaped(&mut self, backslash: u64) -> u64 {
+        let backslash = backslash & !self.prev_escaped;
+        let follows_escape = backslash << 1 | self.prev_escaped;
+        let odd_sequence_starts = backslash & !EVEN_BITS & !follows_escape;
+        let (sequences_starting_on_even_bits, overflow) =
+            odd_sequence_starts.overflowing_add(backslash);
+        self.prev_escaped = overflow as u64;
+        let invert_mask = sequences_starting_on_even_bits << 1;
+        (EVEN_BITS ^ invert_mask) & follows_escape
+    }
+
+    /// Returns the structural starts of a block: operators, opening quotes, and
+    /// the first byte of every number or literal
+    fn next(&mut self, block: &Block) -> u64 {
+        let quote = block.quote & !self.escaped(block.backslash);
+        // Everything from an opening quote up to, but not including, its closing quote
+        let in_string = prefix_xor(quote) ^ self.prev_in_string;
+        self.prev_in_string = ((in_string as i64) >> 63) as u64;
+
+        let scalar = !(block.op | block.whitespace);
+        let nonquote_scalar = scalar & !quote;
+        let follows_nonquote_scalar = nonquote_scalar << 1 | self.prev_scalar;
+        self.prev_scalar = nonquote_scalar >> 63;
+
+        let string_tail = in_string ^ quote;
+        (block.op | (scalar & !follows_nonquote_scalar)) & !string_tail
+    }
+}
+
+/// Computes, for every bit, the xor of it and all the bits below it
+fn prefix_xor(mut mask: u64) -> u64 {
+    mask ^= mask << 1;
+    mask ^= mask << 2;
+    mask ^= mask << 4;
+    mask ^= mask << 8;
+    mask ^= mask << 16;
+    mask ^= mask << 32;
+    mask
+}
+
+/// Stage 1: the offsets of all structural characters and values in `json`.
+///
+/// Fails with the offset of the end if a string is never closed.
+pub fn index(json: &[u8]) -> Result<StdVec<u32>, (ParseError, usize)> {
+    let mut indices = StdVec::with_capacity(json.len() / 8);
+    let mut scanner = Scanner::default();
+    let mut chunks = json.chunks_exact(64);
+    let mut offset = 0;
+    let mut push = |mut structurals: u64, offset: usize| {
+        while structurals != 0 {
+            indices.push((offset + structurals.trailing_zeros() as usize) as u32);
+            structurals &= structurals - 1;
+        }
+    };
+    for chunk in &mut chunks {
+        let block = Block::classify(chunk.try_into().unwrap());
+        push(scanner.next(&block), offset);
+        offset += 64;
+    }
+    let remainder = chunks.remainder();
+    if !remainder.is_empty() {
+        let mut padded = [b' '; 64];
+        padded[..remainder.len()].copy_from_slice(remainder);
+        let block = Block::classify(&padded);
+        push(scanner.next(&block), offset);
+    }
+    if scanner.prev_in_string != 0 {
+        return Err((ParseError::UnexpectedEndOfFile, json.len()));
+    }
+    Ok(indices)
+}
+
+/// Stage 2: walks the structural index to build the tree
+pub(crate) struct Builder<'a, 'i, 'bump> {
+    json: SliceIter<'a, u8>,
+    indices: std::slice::Iter<'i, u32>,
+    alloc: &'bump Bump,
+    ctx: ParseContext,
+    /// The containers being filled, innermost last
+    open: StdVec<Open<'bump>>,
+}
+
+enum Open<'bump> {
+    Array(Vec<'bump, JsonValue<'bump>>),
+    /// With the key its next value goes under
+    Object(JsonObject<'bump>, &'bump str),
+}
+
+impl<'a, 'i, 'bump> Builder<'a, 'i, 'bump> {
+    pub(crate) fn new(
+        json: &'a [u8],
+        indices: &'i [u32],
+        alloc: &'bump Bump,
+        ctx: ParseContext,
+    ) -> Self {
+        Self {
+            json: SliceIter::new(json),
+            indices: indices.iter(),
+            alloc,
+            ctx,
+            open: StdVec::new(),
+        }
+    }
+
+    /// The offset reached so far, to report where an error happened
+    pub fn offset(&self) -> usize {
+        self.json.index()
+    }
+
+    /// Moves to the next structural and returns its byte
+    fn next(&mut self) -> Option<u8> {
+        let index = *self.indices.next()? as usize;
+        self.json.set_index(index);
+        self.json.peek_copy()
+    }
+
+    fn consume(&mut self) -> Option<u8> {
+        let byte = self.next();
+        self.json.ignore_next();
+        byte
+    }
+
+    /// Builds the tree without recursion, so nesting depth is only limited
+    /// by memory
+    pub fn build(&mut self) -> JsonResult<JsonValue<'bump>> {
+        let mut byte = match self.next() {
+            Some(byte) => byte,
+            None => return Ok(JsonValue::Null),
+        };
+        loop {
+            let mut value = match byte {
+                b'[' => {
+                    self.json.ignore_next();
+                    match self.next() {
+                        Some(b']') => JsonValue::Array(self.alloc.alloc(Vec::new_in(self.alloc))),
+                        Some(next) => {
+                            self.open.push(Open::Array(Vec::new_in(self.alloc)));
+                            byte = next;
+                            continue;
+                        }
+                        None => return Err(ParseError::UnexpectedEndOfFile),
+                    }
+                }
+                b'{' => {
+                    self.json.ignore_next();
+                    let contents = HashMap::new_in(BumpWrapper(self.alloc));
+                    if self.next() == Some(b'}') {
+                        JsonValue::Object(self.alloc.alloc(contents))
+                    } else {
+                        let key = self.key()?;
+                        self.open.push(Open::Object(contents, key));
+                        byte = self.next_value()?;
+                        continue;
+                    }
+                }
+                _ => self.scalar(byte)?,
+            };
+            // A value was completed, add it to its container and move on to
+            // the next one
+            loop {
+                let close = match self.open.last_mut() {
+                    None => return Ok(value),
+                    Some(Open::Array(contents)) => {
+                        contents.push(value);
+                        b']'
+                    }
+                    Some(Open::Object(contents, key)) => {
+                        contents.insert(*key, value);
+                        b'}'
+                    }
+                };
+                match self.consume() {
+                    Some(b',') => {
+                        if close == b'}' {
+                            self.next();
+                            let next_key = self.key()?;
+                            if let Some(Open::Object(_, key)) = self.open.last_mut() {
+                                *key = next_key;
+                            }
+                        }
+                        byte = self.next_value()?;
+                        break;
+                    }
+                    Some(c) if c == close => {
+                        value = match self.open.pop() {
+                            Some(Open::Array(mut contents)) => {
+                                contents.shrink_to_fit();
+                                JsonValue::Array(self.alloc.alloc(contents))
+                            }
+                            Some(Open::Object(contents, _)) => {
+                                JsonValue::Object(self.alloc.alloc(contents))
+                            }
+                            None => unreachable!(),
+                        };
+                    }
+                    v if close == b']' => {
+                        return Err(ParseError::ExpectedEndOfArray {
+                            found: v.map(|v| v as char),
+                        })
+                    }
+                    v => {
+                        return Err(ParseError::ExpectedEndOfObject {
+                            found: v.map(|i| i as char),
+                        })
+                    }
+                }
+            }
+        }
+    }
+
+    fn next_value(&mut self) -> JsonResult<u8> {
+        self.next().ok_or(ParseError::UnexpectedEndOfFile)
+    }
+
+    /// Reads an object key and the colon after it
+    fn key(&mut self) -> JsonResult<&'bump str> {
+        let key = read_key_str(&mut self.json, self.alloc, self.ctx)?;
+        let c = self.consume();
+        if c != Some(b':') {
+            return Err(ParseError::ExpectedColon {
+                found: c.map(|i| i as char),
+            });
+        }
+        Ok(key)
+    }
+
+    fn scalar(&mut self, byte: u8) -> JsonResult<JsonValue<'bump>> {
+        let alloc = self.alloc;
+        match byte {
+            b'"' => {
+                if self.ctx.borrow_strings {
+                    if let Some(str) = read_borrowed_str_with(&mut self.json, self.ctx)? {
+                        // SAFETY: `borrow_strings` is only set by `parse_borrowed`,
+                        // whose input outlives `'bump`
+                        let str = unsafe { std::mem::transmute::<&'a str, &'bump str>(str) };
+                        return Ok(JsonValue::Str(alloc.alloc(str)));
+                    }
+                }
+                Ok(read_string_with(&mut self.json, alloc, self.ctx)?.into_value(alloc))
+            }
+            b't' => self.literal(b"true", JsonValue::Boolean(true)),
+            b'f' => self.literal(b"false", JsonValue::Boolean(false)),
+            b'n' => self.literal(b"null", JsonValue::Null),
+            b'0'..=b'9' | b'-' => {
+                let number = self.ctx.number(read_number(&mut self.json)?)?;
+                self.scalar_end(JsonValue::Number(number))
+            }
+            _ => Err(ParseError::ExpectedNextValue {
+                found: self.json.next().map(|i| i as char),
+            }),
+        }
+    }
+
+    fn literal(&mut self, literal: &[u8], value: JsonValue<'bump>) -> JsonResult<JsonValue<'bump>> {
+        match self.json.peek_many_ref(literal.len()) {
+            Some(bytes) if bytes == literal => {
+                self.json.ignore_many(literal.len());
+                self.scalar_end(value)
+            }
+            Some(_) => Err(ParseError::ExpectedNextValue {
+                found: self.json.next().map(|i| i as char),
+            }),
+            None => Err(ParseError::UnexpectedEndOfFile),
+        }
+    }
+
+    /// Stage 1 doesn't split `truex` or `12a`, so check that the scalar really
+    /// ended where its reader stopped
+    fn scalar_end(&mut self, value: JsonValue<'bump>) -> JsonResult<JsonValue<'bump>> {
+        match self.json.peek_copy() {
+            None | Some(b'{' | b'}' | b'[' | b']' | b':' | b',') => Ok(value),
+            Some(byte) if is_whitespace(byte) => Ok(value),
+            Some(byte) => Err(ParseError::ExpectedNextValue {
+                found: Some(byte as char),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, parse_with_options, Backend, ParseOptions};
+
+    /// Finds escaped bytes one at a time, to check the bit-parallel version
+    fn escaped_scalar(bytes: &[u8]) -> StdVec<bool> {
+        let mut escaped = vec![false; bytes.len()];
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] == b'\\' && i + 1 < bytes.len() {
+                escaped[i + 1] = true;
+                i += 2;
+            } else {
+                i += 1;
+            }
+        }
+        escaped
+    }
+
+    #[test]
+    fn escapes_across_blocks() {
+        let mut seed: u32 = 12345;
+        for _ in 0..200 {
+            let bytes: StdVec<u8> = (0..256)
+                .map(|_| {
+                    seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                    if (seed >> 16) % 3 == 0 {
+                        b'a'
+                    } else {
+                        b'\\'
+                    }
+                })
+                .collect();
+            let expected = escaped_scalar(&bytes);
+            let mut scanner = Scanner::default();
+            for (i, chunk) in bytes.chunks_exact(64).enumerate() {
+                let block = Block::classify(chunk.try_into().unwrap());
+                let escaped = scanner.escaped(block.backslash);
+                for bit in 0..64 {
+                    assert_eq!(escaped >> bit & 1 == 1, expected[i * 64 + bit]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn same_as_streaming() {
+        let options = ParseOptions {
+            backend: Backend::Structural,
+            ..Default::default()
+        };
+        let mut long = "[5   ,\n\n".repeat(40);
+        long.push_str("{\"key \\\\\\\"with\\\\\": [\"a\\\\\", \"\\\"{[\"], \"n\": -1.5e3}");
+        long.push_str(&"]".repeat(40));
+        for json in [
+            long.as_str(),
+            "  {\"a\": [1, 2, {\"b\": null}], \"c\": \"d\", \"e\": true, \"f\": false}",
+            "\"string, \\\"string\\\", string—🎸\\uD83E\\uDD95\\u3ED8\\u0003\\f\"",
+            "[[], {}, [[]], \"\", 0]",
+            "",
+            "12",
+            std::str::from_utf8(include_bytes!("../../tests/food.json")).unwrap(),
+        ] {
+            let bump = Bump::new();
+            let expected = parse(json.as_bytes(), &bump).unwrap();
+            let actual = parse_with_options(json.as_bytes(), &bump, &options).unwrap();
+            assert_eq!(actual, expected, "{json}");
+        }
+        for json in [
+            "[1, 2",
+            "[truex]",
+            "[12a]",
+            "{\"a\" 1}",
+            "{\"a\": 1,}",
+            "[1,]",
+            "\"open",
+            "[\"a\" \"b\"]",
+        ] {
+            let bump = Bump::new();
+            assert!(
+                parse_with_options(json.as_bytes(), &bump, &options).is_err(),
+                "{json}"
+            );
+        }
+        let bump = Bump::new();
+        let deep = "[".repeat(100_000) + &"]".repeat(100_000);
+        parse_with_options(deep.as_bytes(), &bump, &options).unwrap();
+    }
+}
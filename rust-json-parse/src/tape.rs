@@ -0,0 +1,559 @@
+//! A flat alternative to the `JsonValue` tree.
+//!
+//! Every value is one or two `u64` words on a single tape, with the contents
+//! of strings in a separate byte buffer, so a whole document is two
+//! allocations instead of one per array, object and string. The top byte of a
+//! word is its tag and the low 56 bits its payload:
+//!
+//! - `n`, `t`, `f`: null, true and false, no payload
+//! - `d`: a number, whose `f64` bits are the next word
+//! - `"`: a string, the payload is the offset in the string buffer of its
+//!   length as a little-endian `u32`, followed by its bytes
+//! - `[`, `{`: the start of an array or object, the low 32 bits are the index
+//!   just past its closing word and the next 24 bits its number of elements
+//!   (saturating at `COUNT_MAX`)
+//! - `]`, `}`: the end of an array or object, the payload is the index of its
+//!   opening word
+//!
+//! Objects alternate between a string word for a key and the key's value.
+
+use std::str;
+use std::vec::Vec as StdVec;
+
+use bumpalo::collections::{String, Vec};
+use bumpalo::Bump;
+use hashbrown::{BumpWrapper, HashMap};
+
+use crate::parsers::number::read_number;
+use crate::parsers::string::{read_borrowed_str, read_string};
+use crate::slice_iter::{CopyIter, SliceIter};
+use crate::{ignore_ws, parse_error, JsonError, JsonObject, JsonResult, JsonValue, ParseError};
+
+const TAG_SHIFT: u32 = 56;
+const PAYLOAD_MASK: u64 = (1 << TAG_SHIFT) - 1;
+const COUNT_SHIFT: u32 = 32;
+const COUNT_MAX: u64 = (1 << (TAG_SHIFT - COUNT_SHIFT)) - 1;
+
+pub struct Tape {
+    words: StdVec<u64>,
+    strings: StdVec<u8>,
+}
+
+impl Tape {
+    /// Parses `json_buf` onto a new tape
+    pub fn parse(json_buf: &[u8]) -> Result<Self, JsonError> {
+        if json_buf.len() >= 0x20000000 {
+            return Err(JsonError::FileTooLong {
+                len: json_buf.len(),
+            });
+        }
+        let mut builder = TapeBuilder {
+            json: SliceIter::new(json_buf),
+            tape: Tape {
+                words: StdVec::with_capacity(json_buf.len() / 8),
+                strings: StdVec::with_capacity(json_buf.len() / 4),
+            },
+            scratch: Bump::new(),
+            open: StdVec::new(),
+        };
+        match builder.build() {
+            Ok(()) => {
+                let mut tape = builder.tape;
+                tape.words.shrink_to_fit();
+                tape.strings.shrink_to_fit();
+                Ok(tape)
+            }
+            Err(e) => Err(parse_error(e, &json_buf[builder.json.index()..])),
+        }
+    }
+
+    pub fn root(&self) -> TapeRef<'_> {
+        TapeRef {
+            tape: self,
+            index: 0,
+        }
+    }
+
+    /// Builds the `JsonValue` tree of the whole document
+    pub fn to_value<'bump>(&self, alloc: &'bump Bump) -> JsonValue<'bump> {
+        self.root().to_value(alloc)
+    }
+
+    /// Bytes used by the tape and string buffer
+    pub fn size(&self) -> usize {
+        self.words.len() * 8 + self.strings.len()
+    }
+
+    fn tag(&self, index: usize) -> u8 {
+        (self.words[index] >> TAG_SHIFT) as u8
+    }
+
+    fn payload(&self, index: usize) -> u64 {
+        self.words[index] & PAYLOAD_MASK
+    }
+
+    /// Index of the word after the value starting at `index`
+    fn next_index(&self, index: usize) -> usize {
+        match self.tag(index) {
+            b'[' | b'{' => self.payload(index) as u32 as usize,
+            b'd' => index + 2,
+            _ => index + 1,
+        }
+    }
+
+    fn str_at(&self, index: usize) -> &str {
+        let offset = self.payload(index) as usize;
+        let len = u32::from_le_bytes(self.strings[offset..offset + 4].try_into().unwrap());
+        let bytes = &self.strings[offset + 4..offset + 4 + len as usize];
+        // SAFETY: strings are validated as UTF-8 before they are put on the tape
+        unsafe { str::from_utf8_unchecked(bytes) }
+    }
+}
+
+/// A cursor pointing at one value on a `Tape`
+#[derive(Clone, Copy)]
+pub struct TapeRef<'t> {
+    tape: &'t Tape,
+    index: usize,
+}
+
+impl<'t> TapeRef<'t> {
+    pub fn is_null(&self) -> bool {
+        self.tape.tag(self.index) == b'n'
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self.tape.tag(self.index) {
+            b't' => Some(true),
+            b'f' => Some(false),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self.tape.tag(self.index) {
+            b'd' => Some(f64::from_bits(self.tape.words[self.index + 1])),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&'t str> {
+        match self.tape.tag(self.index) {
+            b'"' => Some(self.tape.str_at(self.index)),
+            _ => None,
+        }
+    }
+
+    pub fn is_array(&self) -> bool {
+        self.tape.tag(self.index) == b'['
+    }
+
+    pub fn is_object(&self) -> bool {
+        self.tape.tag(self.index) == b'{'
+    }
+
+    /// The number of elements or members, if this is an array or object
+    pub fn len(&self) -> Option<usize> {
+        if !self.is_array() && !self.is_object() {
+            return None;
+        }
+        let count = self.tape.payload(self.index) >> COUNT_SHIFT;
+        if count < COUNT_MAX {
+            return Some(count as usize);
+        }
+        if self.is_array() {
+            return Some(self.elements().count());
+        }
+        return Some(self.members().count());
+    }
+
+    pub fn is_empty(&self) -> Option<bool> {
+        self.len().map(|len| len == 0)
+    }
+
+    /// Iterates over the elements of an array, empty for anything else
+    pub fn elements(&self) -> Elements<'t> {
+        let (index, end) = self.contents(b'[');
+        Elements {
+            tape: self.tape,
+            index,
+            end,
+        }
+    }
+
+    /// Iterates over the keys and values of an object, empty for anything else
+    pub fn members(&self) -> Members<'t> {
+        let (index, end) = self.contents(b'{');
+        Members {
+            tape: self.tape,
+            index,
+            end,
+        }
+    }
+
+    /// The element at `index`, if this is an array that long
+    pub fn at(&self, index: usize) -> Option<TapeRef<'t>> {
+        self.elements().nth(index)
+    }
+
+    /// The value of the last `key` member, if this is an object that has it
+    pub fn get(&self, key: &str) -> Option<TapeRef<'t>> {
+        self.members()
+            .filter(|(k, _)| *k == key)
+            .last()
+            .map(|(_, v)| v)
+    }
+
+    /// Copies this value into a `JsonValue` tree, walking the tape in order
+    /// without recursion, so nesting depth is only limited by memory
+    pub fn to_value<'bump>(&self, alloc: &'bump Bump) -> JsonValue<'bump> {
+        let tape = self.tape;
+        let mut open: StdVec<Open<'bump>> = StdVec::new();
+        let mut index = self.index;
+        loop {
+            let at = TapeRef { tape, index };
+            let value = match tape.tag(index) {
+                b'[' => {
+                    let contents = Vec::with_capacity_in(at.len().unwrap(), alloc);
+                    open.push(Open::Array(contents));
+                    index += 1;
+                    continue;
+                }
+                b'{' => {
+                    let contents = HashMap::with_capacity_in(at.len().unwrap(), BumpWrapper(alloc));
+                    open.push(Open::Object(contents, None));
+                    index += 1;
+                    continue;
+                }
+                b'"' if matches!(open.last(), Some(Open::Object(_, None))) => {
+                    if let Some(Open::Object(_, key)) = open.last_mut() {
+                        *key = Some(&*alloc.alloc_str(tape.str_at(index)));
+                    }
+                    index += 1;
+                    continue;
+                }
+                b']' | b'}' => {
+                    index += 1;
+                    match open.pop() {
+                        Some(Open::Array(contents)) => JsonValue::Array(alloc.alloc(contents)),
+                        Some(Open::Object(contents, _)) => JsonValue::Object(alloc.alloc(contents)),
+                        None => unreachable!("unbalanced tape"),
+                    }
+                }
+                _ => {
+                    index = tape.next_index(index);
+                    at.scalar(alloc)
+                }
+            };
+            match open.last_mut() {
+                None => return value,
+                Some(Open::Array(contents)) => contents.push(value),
+                Some(Open::Object(contents, key)) => {
+                    contents.insert(key.take().unwrap(), value);
+                }
+            }
+        }
+    }
+
+    fn scalar<'bump>(&self, alloc: &'bump Bump) -> JsonValue<'bump> {
+        match self.tape.tag(self.index) {
+            b'n' => JsonValue::Null,
+            b't' => JsonValue::Boolean(true),
+            b'f' => JsonValue::Boolean(false),
+            b'd' => JsonValue::Number(self.as_f64().unwrap()),
+            b'"' => JsonValue::String(
+                alloc.alloc(String::from_str_in(self.tape.str_at(self.index), alloc)),
+            ),
+            tag => unreachable!("value can't start with {:?}", tag as char),
+        }
+    }
+
+    /// The range of indices between the opening and closing word, if this is
+    /// a container opened by `open`
+    fn contents(&self, open: u8) -> (usize, usize) {
+        if self.tape.tag(self.index) != open {
+            return (0, 0);
+        }
+        (self.index + 1, self.tape.next_index(self.index) - 1)
+    }
+}
+
+/// An array or object being copied by `TapeRef::to_value`, with the key of
+/// the member being copied
+enum Open<'bump> {
+    Array(Vec<'bump, JsonValue<'bump>>),
+    Object(JsonObject<'bump>, Option<&'bump str>),
+}
+
+pub struct Elements<'t> {
+    tape: &'t Tape,
+    index: usize,
+    end: usize,
+}
+
+impl<'t> Iterator for Elements<'t> {
+    type Item = TapeRef<'t>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.end {
+            return None;
+        }
+        let element = TapeRef {
+            tape: self.tape,
+            index: self.index,
+        };
+        self.index = self.tape.next_index(self.index);
+        Some(element)
+    }
+}
+
+pub struct Members<'t> {
+    tape: &'t Tape,
+    index: usize,
+    end: usize,
+}
+
+impl<'t> Iterator for Members<'t> {
+    type Item = (&'t str, TapeRef<'t>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.end {
+            return None;
+        }
+        let key = self.tape.str_at(self.index);
+        let value = TapeRef {
+            tape: self.tape,
+            index: self.index + 1,
+        };
+        self.index = self.tape.next_index(self.index + 1);
+        Some((key, value))
+    }
+}
+
+struct TapeBuilder<'a> {
+    json: SliceIter<'a, u8>,
+    tape: Tape,
+    /// Holds strings with escapes until they're copied onto the tape
+    scratch: Bump,
+    /// Indices of the opening words of the containers being built, and how
+    /// many values each has so far
+    open: StdVec<(usize, u64)>,
+}
+
+impl<'a> TapeBuilder<'a> {
+    /// Parses one value, without recursion, so nesting depth is only limited
+    /// by memory
+    fn build(&mut self) -> JsonResult<()> {
+        'value: loop {
+            ignore_ws(&mut self.json);
+            match self.json.peek_copy() {
+                Some(b'[') => {
+                    self.json.ignore_next();
+                    self.open(b'[');
+                    ignore_ws(&mut self.json);
+                    if self.json.peek_copy() != Some(b']') {
+                        continue 'value;
+                    }
+                    self.json.ignore_next();
+                    self.close(b']');
+                }
+                Some(b'{') => {
+                    self.json.ignore_next();
+                    self.open(b'{');
+                    ignore_ws(&mut self.json);
+                    if self.json.peek_copy() != Some(b'}') {
+                        self.key()?;
+                        continue 'value;
+                    }
+                    self.json.ignore_next();
+                    self.close(b'}');
+                }
+                // An empty document is null, like in `parse`
+                None if self.open.is_empty() => self.push(b'n', 0),
+                _ => self.scalar()?,
+            }
+            // A value was completed, move on to the next one in its container
+            while let Some((open, count)) = self.open.last_mut() {
+                *count += 1;
+                let is_object = self.tape.tag(*open) == b'{';
+                ignore_ws(&mut self.json);
+                match (self.json.next(), is_object) {
+                    (Some(b','), false) => continue 'value,
+                    (Some(b','), true) => {
+                        ignore_ws(&mut self.json);
+                        self.key()?;
+                        continue 'value;
+                    }
+                    (Some(b']'), false) => self.close(b']'),
+                    (Some(b'}'), true) => self.close(b'}'),
+                    (found, false) => {
+                        return Err(ParseError::ExpectedEndOfArray {
+                            found: found.map(|b| b as char),
+                        })
+                    }
+                    (found, true) => {
+                        return Err(ParseError::ExpectedEndOfObject {
+                            found: found.map(|b| b as char),
+                        })
+                    }
+                }
+            }
+            return Ok(());
+        }
+    }
+
+    fn push(&mut self, tag: u8, payload: u64) {
+        self.tape.words.push((tag as u64) << TAG_SHIFT | payload);
+    }
+
+    fn open(&mut self, tag: u8) {
+        self.open.push((self.tape.words.len(), 0));
+        self.push(tag, 0);
+    }
+
+    fn close(&mut self, tag: u8) {
+        let (open, count) = self.open.pop().unwrap();
+        self.push(tag, open as u64);
+        let end = self.tape.words.len() as u64;
+        self.tape.words[open] |= count.min(COUNT_MAX) << COUNT_SHIFT | end;
+    }
+
+    fn key(&mut self) -> JsonResult<()> {
+        self.string()?;
+        ignore_ws(&mut self.json);
+        match self.json.next() {
+            Some(b':') => Ok(()),
+            found => Err(ParseError::ExpectedColon {
+                found: found.map(|b| b as char),
+            }),
+        }
+    }
+
+    fn string(&mut self) -> JsonResult<()> {
+        let offset = self.tape.strings.len() as u64;
+        match read_borrowed_str(&mut self.json)? {
+            Some(str) => Self::push_str_in(&mut self.tape.strings, str),
+            None => {
+                let str = read_string(&mut self.json, &self.scratch)?;
+                Self::push_str_in(&mut self.tape.strings, &str);
+                drop(str);
+                self.scratch.reset();
+            }
+        }
+        self.push(b'"', offset);
+        Ok(())
+    }
+
+    fn push_str_in(strings: &mut StdVec<u8>, str: &str) {
+        strings.extend_from_slice(&(str.len() as u32).to_le_bytes());
+        strings.extend_from_slice(str.as_bytes());
+    }
+
+    fn scalar(&mut self) -> JsonResult<()> {
+        match self.json.peek_copy() {
+            Some(b'"') => return self.string(),
+            Some(b'-' | b'0'..=b'9') => {
+                let num = read_number(&mut self.json)?;
+                self.push(b'd', 0);
+                self.tape.words.push(num.to_bits());
+                return Ok(());
+            }
+            None => return Err(ParseError::UnexpectedEndOfFile),
+            _ => {}
+        }
+        for (literal, tag) in [(&b"true"[..], b't'), (b"false", b'f'), (b"null", b'n')] {
+            if self.json.peek_many_ref(literal.len()) == Some(literal) {
+                self.json.ignore_many(literal.len());
+                self.push(tag, 0);
+                return Ok(());
+            }
+        }
+        Err(ParseError::ExpectedNextValue {
+            found: self.json.next().map(|b| b as char),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn navigate() {
+        let json =
+            br#" {"a": [1, -2.5e3, "x\ny", true, false, null, [], {}], "b": {"c": "d"}, "a": 0} "#;
+        let tape = Tape::parse(json).unwrap();
+        let root = tape.root();
+        assert!(root.is_object());
+        assert_eq!(root.len(), Some(3));
+        // The last duplicate key wins, as in a parsed `JsonObject`
+        assert_eq!(root.get("a").unwrap().as_f64(), Some(0.0));
+        let a = root.members().next().unwrap().1;
+        assert_eq!(a.len(), Some(8));
+        assert_eq!(a.at(1).unwrap().as_f64(), Some(-2500.0));
+        assert_eq!(a.at(2).unwrap().as_str(), Some("x\ny"));
+        assert_eq!(a.at(3).unwrap().as_bool(), Some(true));
+        assert!(a.at(5).unwrap().is_null());
+        assert_eq!(a.at(6).unwrap().len(), Some(0));
+        assert!(a.at(8).is_none());
+        assert_eq!(
+            root.get("b").and_then(|b| b.get("c")).unwrap().as_str(),
+            Some("d")
+        );
+        assert!(root.get("c").is_none());
+        assert!(root.at(0).is_none());
+    }
+
+    #[test]
+    fn same_as_parse() {
+        let inputs: [&[u8]; 5] = [
+            include_bytes!("../../tests/food.json"),
+            br#"[[[["deep"]]], {"k": {"l": [1, 2, {"m": null}]}}]"#,
+            b"\"top level\"",
+            b"42",
+            b"",
+        ];
+        for json in inputs {
+            let bump = Bump::new();
+            let tape = Tape::parse(json).unwrap();
+            assert_eq!(tape.to_value(&bump), parse(json, &bump).unwrap());
+        }
+    }
+
+    #[test]
+    fn errors() {
+        for json in [
+            &b"[1, 2"[..],
+            b"{\"a\" 1}",
+            b"{\"a\": 1,}",
+            b"[1 2]",
+            b"[nul]",
+            b"{1: 2}",
+        ] {
+            assert!(Tape::parse(json).is_err(), "{:?}", str::from_utf8(json));
+        }
+        let deep = "[".repeat(100_000) + &"]".repeat(100_000);
+        assert_eq!(Tape::parse(deep.as_bytes()).unwrap().root().len(), Some(1));
+    }
+
+    #[test]
+    fn deep_to_value() {
+        let bump = Bump::new();
+        let deep = r#"{"a": ["#.repeat(100_000) + "1" + &"]}".repeat(100_000);
+        let tape = Tape::parse(deep.as_bytes()).unwrap();
+        let mut value = &tape.to_value(&bump);
+        // Walked down by hand, as comparing trees recurses
+        for _ in 0..100_000 {
+            let JsonValue::Object(obj) = value else {
+                panic!("expected an object");
+            };
+            let Some(JsonValue::Array(arr)) = obj.get("a") else {
+                panic!("expected an array");
+            };
+            assert_eq!(arr.len(), 1);
+            value = &arr[0];
+        }
+        assert_eq!(*value, JsonValue::Number(1.0));
+    }
+}
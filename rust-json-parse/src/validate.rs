@@ -0,0 +1,234 @@
+//! Checking that input is JSON without building anything from it.
+
+use crate::parsers::number::read_number;
+use crate::parsers::string::validate_string;
+use crate::slice_iter::{CopyIter, SliceIter};
+use crate::{ignore_ws, JsonResult, ParseError};
+
+/// Checks that `json` is exactly one JSON value, surrounded by nothing but
+/// whitespace, without allocating.
+///
+/// Accepts what `parse` does, except that it's stricter about the ends of
+/// the input: empty input and anything after the value are errors here.
+/// Strings are scanned with the same SIMD routines, and checked for UTF-8 a
+/// run at a time. Nesting is tracked without recursion, a bit per level, so
+/// only input nested more than 64 levels deep allocates.
+pub fn validate(json: &[u8]) -> Result<(), ParseError> {
+    let mut json = SliceIter::new(json);
+    let mut open = Nesting::default();
+    'value: loop {
+        ignore_ws(&mut json);
+        match json.peek_copy() {
+            Some(b'[') => {
+                json.ignore_next();
+                ignore_ws(&mut json);
+                if json.peek_copy() != Some(b']') {
+                    open.push(false);
+                    continue 'value;
+                }
+                json.ignore_next();
+            }
+            Some(b'{') => {
+                json.ignore_next();
+                ignore_ws(&mut json);
+                if json.peek_copy() != Some(b'}') {
+                    open.push(true);
+                    validate_key(&mut json)?;
+                    continue 'value;
+                }
+                json.ignore_next();
+            }
+            _ => validate_scalar(&mut json)?,
+        }
+        // A value was completed, move on to the next one in its container
+        while let Some(is_object) = open.last() {
+            ignore_ws(&mut json);
+            match (json.next(), is_object) {
+                (Some(b','), false) => continue 'value,
+                (Some(b','), true) => {
+                    ignore_ws(&mut json);
+                    validate_key(&mut json)?;
+                    continue 'value;
+                }
+                (Some(b']'), false) | (Some(b'}'), true) => open.pop(),
+                (found, false) => {
+                    return Err(ParseError::ExpectedEndOfArray {
+                        found: found.map(|c| c as char),
+                    })
+                }
+                (found, true) => {
+                    return Err(ParseError::ExpectedEndOfObject {
+                        found: found.map(|c| c as char),
+                    })
+                }
+            }
+        }
+        break;
+    }
+    ignore_ws(&mut json);
+    match json.peek_copy() {
+        Some(found) => Err(ParseError::TrailingCharacters {
+            found: found as char,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Whether each open container is an object, a bit per level with the
+/// innermost in the lowest bit of `bits`. Every 64 levels spill onto the heap
+#[derive(Default)]
+struct Nesting {
+    bits: u64,
+    depth: usize,
+    spilled: Vec<u64>,
+}
+
+impl Nesting {
+    fn push(&mut self, is_object: bool) {
+        if self.depth > 0 && self.depth % 64 == 0 {
+            self.spilled.push(self.bits);
+            self.bits = 0;
+        }
+        self.bits = self.bits << 1 | is_object as u64;
+        self.depth += 1;
+    }
+
+    fn pop(&mut self) {
+        self.bits >>= 1;
+        self.depth -= 1;
+        if self.depth > 0 && self.depth % 64 == 0 {
+            self.bits = self.spilled.pop().unwrap();
+        }
+    }
+
+    fn last(&self) -> Option<bool> {
+        (self.depth > 0).then_some(self.bits & 1 == 1)
+    }
+}
+
+fn validate_key(json: &mut SliceIter<'_, u8>) -> JsonResult<()> {
+    validate_string(json)?;
+    ignore_ws(json);
+    match json.next() {
+        Some(b':') => Ok(()),
+        found => Err(ParseError::ExpectedColon {
+            found: found.map(|c| c as char),
+        }),
+    }
+}
+
+fn validate_scalar(json: &mut SliceIter<'_, u8>) -> JsonResult<()> {
+    match json.peek_copy() {
+        Some(b'"') => validate_string(json),
+        Some(b'-' | b'0'..=b'9') => read_number(json).map(|_| ()),
+        Some(_) => {
+            for literal in [&b"true"[..], b"false", b"null"] {
+                if json.peek_many_ref(literal.len()) == Some(literal) {
+                    json.ignore_many(literal.len());
+                    return Ok(());
+                }
+            }
+            Err(ParseError::ExpectedNextValue {
+                found: json.peek_copy().map(|c| c as char),
+            })
+        }
+        None => Err(ParseError::UnexpectedEndOfFile),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use bumpalo::Bump;
+
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn agrees_with_parse() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../tests");
+        let mut files = 0;
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            let json = fs::read(&path).unwrap();
+            let bump = Bump::new();
+            assert_eq!(
+                validate(&json).is_ok(),
+                parse(&json, &bump).is_ok(),
+                "{path:?}"
+            );
+            files += 1;
+        }
+        assert!(files > 0);
+
+        let bump = Bump::new();
+        for json in [
+            "[1, -2.5e3, \"a\\\"b\\u00e9\\ud83d\\ude00é\", {\"k\": [true, false, null]}, {}, []]",
+            " \"\" ",
+            "[1 2]",
+            "[1,]",
+            "{\"a\" 1}",
+            "{\"a\": 1,}",
+            "{1: 2}",
+            "[01]",
+            "[tru]",
+            "\"\\ud800\"",
+            "\"\\udc00\"",
+            "\"\\x\"",
+            "\"\t\"",
+            "[\"a\"",
+            "{\"a\": [1, {\"b\": nul}]}",
+        ] {
+            let parsed = parse(json.as_bytes(), &bump);
+            assert_eq!(validate(json.as_bytes()).is_ok(), parsed.is_ok(), "{json}");
+        }
+    }
+
+    #[test]
+    fn ends() {
+        assert!(matches!(
+            validate(b""),
+            Err(ParseError::UnexpectedEndOfFile)
+        ));
+        assert!(matches!(
+            validate(b"[1] x"),
+            Err(ParseError::TrailingCharacters { found: 'x' })
+        ));
+        assert!(matches!(
+            validate(b"\"\xFF\""),
+            Err(ParseError::InvalidUtf8 { .. })
+        ));
+        validate(b" \n[1]\t ").unwrap();
+    }
+
+    #[test]
+    fn deep() {
+        let deep = "[{\"a\": ".repeat(100_000) + "1" + &"}]".repeat(100_000);
+        validate(deep.as_bytes()).unwrap();
+        let bump = Bump::new();
+        let nested = "[[{\"a\": [{\"b\": {}}, [[]]]}], {}]";
+        assert_eq!(
+            validate(nested.as_bytes()).is_ok(),
+            parse(nested.as_bytes(), &bump).is_ok()
+        );
+        assert!(matches!(
+            validate("[".repeat(1_000_000).as_bytes()),
+            Err(ParseError::UnexpectedEndOfFile)
+        ));
+        let mismatched = "[".repeat(70) + "{\"a\": 1" + &"]".repeat(71);
+        assert!(matches!(
+            validate(mismatched.as_bytes()),
+            Err(ParseError::ExpectedEndOfObject { found: Some(']') })
+        ));
+        let mismatched = "[".repeat(70) + "{}" + &"]".repeat(69) + "}";
+        assert!(matches!(
+            validate(mismatched.as_bytes()),
+            Err(ParseError::ExpectedEndOfArray { found: Some('}') })
+        ));
+    }
+}
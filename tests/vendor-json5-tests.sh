@@ -0,0 +1,19 @@
+#!/bin/sh
+# Vendors the upstream JSON5 test suite into tests/json5, with its license and
+# the commit it was taken from. Pass a commit or tag to pin one, otherwise
+# the default branch is used.
+set -e
+cd "$(dirname "$0")"
+tmp=$(mktemp -d)
+trap 'rm -rf "$tmp"' EXIT
+git clone --quiet https://github.com/json5/json5-tests "$tmp"
+if [ -n "$1" ]; then
+    git -C "$tmp" checkout --quiet "$1"
+fi
+rm -rf json5
+mkdir json5
+for dir in "$tmp"/*/; do
+    cp -R "$dir" json5/
+done
+cp "$tmp"/LICENSE* "$tmp"/README* json5/
+git -C "$tmp" rev-parse HEAD > json5/REVISION